@@ -0,0 +1,585 @@
+use crate::{
+    api::{self, session::Context, ClientResponse, Route},
+    app::{self, SanitizedError},
+    model,
+};
+use chrono::{DateTime, Utc};
+use rocket::fairing::AdHoc;
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+pub const ADMIN_ROUTE_BASE: &str = "/admin";
+pub struct AdminRoute {}
+
+impl Route for AdminRoute {
+    fn stage() -> AdHoc {
+        AdHoc::on_ignite("Admin Routing", |rocket| async {
+            rocket.mount(
+                ADMIN_ROUTE_BASE,
+                rocket::routes![get_adjustment, get_trial_balance, post_adjustment, rebuild_client, verify_all, verify_client],
+            )
+        })
+    }
+}
+
+// A Context for requests that carry the configured admin token as a bearer token. Requests
+// without it, or any request if no token is configured, fail with a 401.
+pub struct AdminContext(Context);
+
+impl AdminContext {
+    pub fn session(&self) -> app::Session {
+        self.0.session()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for AdminContext {
+    type Error = String;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let state = match api::State::fetch(req.rocket()) {
+            Some(state) => state,
+            None => return Outcome::Failure((Status::InternalServerError, "internal error".to_string())),
+        };
+
+        let expected = state.app.config().admin_token.as_deref().filter(|t| !t.is_empty());
+        let token = req.headers().get_one("Authorization").and_then(|v| v.strip_prefix("Bearer "));
+        match (expected, token) {
+            (Some(expected), Some(token)) if constant_time_eq(expected.as_bytes(), token.as_bytes()) => {}
+            _ => return Outcome::Failure((Status::Unauthorized, "invalid admin token".to_string())),
+        }
+
+        return Context::from_request(req).await.map(AdminContext);
+    }
+}
+
+// Compares digests of the tokens without returning early on the first difference, so that the
+// time taken tells neither how much of a guessed token is right nor how long the token is.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    let (a, b) = (Sha256::digest(a), Sha256::digest(b));
+    return a.iter().zip(b.iter()).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0;
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "camelCase")]
+pub struct PostAdjustment {
+    #[serde(rename = "type")]
+    pub record_type: model::RecordType,
+    pub client: u16,
+    pub tx: u32,
+    // only credits and debits have an amount
+    #[serde(default)]
+    pub amount: model::Amount,
+    // the client account the adjustment applies to, the default currency if unset
+    #[serde(default)]
+    pub currency: model::Currency,
+    pub reason: String,
+}
+
+impl From<PostAdjustment> for model::Adjustment {
+    fn from(adjustment: PostAdjustment) -> Self {
+        let now = Utc::now();
+        let transaction = model::Transaction {
+            record_type: adjustment.record_type,
+            client: adjustment.client,
+            tx: adjustment.tx,
+            amount: adjustment.amount,
+            currency: adjustment.currency,
+            to_currency: None,
+            conversion: None,
+            charged_for: None,
+
+            creation_time: now,
+            revision_number: 1,
+            revision_time: now,
+        };
+        return model::Adjustment::new(transaction, adjustment.reason);
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "camelCase")]
+pub struct AdjustmentResponse {
+    #[serde(rename = "type")]
+    pub record_type: model::RecordType,
+    pub client: u16,
+    pub tx: u32,
+    pub amount: model::Amount,
+    pub currency: model::Currency,
+    pub reason: String,
+    pub creation_time: DateTime<Utc>,
+}
+
+impl From<model::Adjustment> for AdjustmentResponse {
+    fn from(adjustment: model::Adjustment) -> Self {
+        return Self {
+            record_type: adjustment.transaction.record_type,
+            client: adjustment.transaction.client,
+            tx: adjustment.transaction.tx,
+            amount: adjustment.transaction.amount,
+            currency: adjustment.transaction.currency,
+            reason: adjustment.reason,
+            creation_time: adjustment.creation_time,
+        };
+    }
+}
+
+// Locks, unlocks, credits or debits a client.
+#[rocket::post("/adjustments", format = "json", data = "<adjustment_json>")]
+pub async fn post_adjustment(adjustment_json: Json<PostAdjustment>, context: AdminContext) -> Result<(), SanitizedError> {
+    let sess = context.session();
+    return sess.process_adjustment(adjustment_json.into_inner().into()).await;
+}
+
+#[rocket::get("/adjustments/<tx>")]
+pub async fn get_adjustment(tx: u32, context: AdminContext) -> Result<Json<AdjustmentResponse>, SanitizedError> {
+    let sess = context.session();
+    if let Some(adjustment) = sess.get_adjustment_by_tx_id(tx).await? {
+        return Ok(Json(adjustment.into()));
+    } else {
+        return Err(SanitizedError::NotFound(format!("Adjustment {tx:} not found.")));
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "camelCase")]
+pub struct DiscrepancyResponse {
+    pub client: u16,
+    pub currency: model::Currency,
+    pub kind: app::DiscrepancyKind,
+    pub stored: String,
+    pub expected: String,
+}
+
+impl From<app::ClientDiscrepancy> for DiscrepancyResponse {
+    fn from(d: app::ClientDiscrepancy) -> Self {
+        return Self {
+            client: d.client,
+            currency: d.currency,
+            kind: d.kind,
+            stored: d.stored,
+            expected: d.expected,
+        };
+    }
+}
+
+// Checks every client account against its history, an empty list means the store is consistent.
+#[rocket::get("/verify")]
+pub async fn verify_all(context: AdminContext) -> Result<Json<Vec<DiscrepancyResponse>>, SanitizedError> {
+    let sess = context.session();
+    let discrepancies = sess.verify_all().await?;
+    return Ok(Json(discrepancies.into_iter().map(DiscrepancyResponse::from).collect()));
+}
+
+#[rocket::get("/verify/<client_id>")]
+pub async fn verify_client(client_id: u16, context: AdminContext) -> Result<Json<Vec<DiscrepancyResponse>>, SanitizedError> {
+    let sess = context.session();
+    if let Some(discrepancies) = sess.verify_client(client_id).await? {
+        return Ok(Json(discrepancies.into_iter().map(DiscrepancyResponse::from).collect()));
+    } else {
+        return Err(SanitizedError::NotFound(format!("Client {client_id:} not found.")));
+    }
+}
+
+// Replaces a client's stored account in a currency with the one replayed from its event log.
+#[rocket::post("/clients/<client_id>/rebuild?<currency>")]
+pub async fn rebuild_client(client_id: u16, currency: Option<String>, context: AdminContext) -> Result<Json<ClientResponse>, SanitizedError> {
+    let sess = context.session();
+    let currency = api::parse_param("currency", &currency)?.unwrap_or_default();
+    if let Some(client) = sess.rebuild_client(client_id, currency).await? {
+        return Ok(Json(client.into()));
+    } else {
+        return Err(SanitizedError::NotFound(format!("Client {client_id:} has no {currency:} events.")));
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "camelCase")]
+pub struct AccountBalanceResponse {
+    pub account: String,
+    pub debit: model::Amount,
+    pub credit: model::Amount,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "camelCase")]
+pub struct CurrencyTotalResponse {
+    pub currency: model::Currency,
+    pub debit: model::Amount,
+    pub credit: model::Amount,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerMismatchResponse {
+    pub account: String,
+    pub ledger: model::Amount,
+    pub stored: model::Amount,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "camelCase")]
+pub struct TrialBalanceResponse {
+    pub accounts: Vec<AccountBalanceResponse>,
+    pub totals: Vec<CurrencyTotalResponse>,
+    pub mismatches: Vec<LedgerMismatchResponse>,
+    pub balanced: bool,
+}
+
+impl From<app::TrialBalance> for TrialBalanceResponse {
+    fn from(t: app::TrialBalance) -> Self {
+        return Self {
+            balanced: t.is_balanced(),
+            accounts: t
+                .accounts
+                .into_iter()
+                .map(|a| AccountBalanceResponse {
+                    account: a.account.to_string(),
+                    debit: a.debit,
+                    credit: a.credit,
+                })
+                .collect(),
+            totals: t
+                .totals
+                .into_iter()
+                .map(|t| CurrencyTotalResponse {
+                    currency: t.currency,
+                    debit: t.debit,
+                    credit: t.credit,
+                })
+                .collect(),
+            mismatches: t
+                .mismatches
+                .into_iter()
+                .map(|m| LedgerMismatchResponse {
+                    account: m.account.to_string(),
+                    ledger: m.ledger,
+                    stored: m.stored,
+                })
+                .collect(),
+        };
+    }
+}
+
+// Totals the journal postings of every ledger account, debits and credits should always be equal
+// in every currency, and the client ledger accounts should match the stored clients.
+#[rocket::get("/trial-balance")]
+pub async fn get_trial_balance(context: AdminContext) -> Result<Json<TrialBalanceResponse>, SanitizedError> {
+    let sess = context.session();
+    return Ok(Json(sess.trial_balance().await?.into()));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{transactions, ErrorResponse, API};
+    use rocket::http::{ContentType, Header};
+    use rocket::local::asynchronous::Client;
+    use rocket::serde::json;
+
+    const TOKEN: &str = "let-me-in";
+
+    async fn post_transaction(client: &Client, record_type: model::RecordType, tx: u32, amount: i32) -> Status {
+        let transaction = transactions::PostTransaction {
+            tx,
+            client: 1,
+            record_type,
+            amount: model::Amount::from(amount),
+            currency: model::Currency::USD,
+            to_currency: None,
+        };
+        let response = client
+            .post(rocket::uri!("/transactions", transactions::post_transaction()))
+            .header(ContentType::JSON)
+            .body(json::to_string(&transaction).unwrap())
+            .dispatch()
+            .await;
+        return response.status();
+    }
+
+    async fn post_adjustment(client: &Client, token: &str, record_type: model::RecordType, tx: u32, amount: i32) -> Status {
+        let adjustment = PostAdjustment {
+            record_type,
+            client: 1,
+            tx,
+            amount: model::Amount::from(amount),
+            reason: "support ticket".to_string(),
+            currency: model::Currency::USD,
+        };
+        let response = client
+            .post(rocket::uri!("/admin", super::post_adjustment()))
+            .header(ContentType::JSON)
+            .header(Header::new("Authorization", format!("Bearer {token:}")))
+            .body(json::to_string(&adjustment).unwrap())
+            .dispatch()
+            .await;
+        return response.status();
+    }
+
+    #[rocket::async_test]
+    async fn test_admin_disabled() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        assert_eq!(post_transaction(&client, model::RecordType::Deposit, 1, 10).await, Status::Ok);
+        assert_eq!(post_adjustment(&client, "", model::RecordType::Lock, 2, 0).await, Status::Unauthorized);
+    }
+
+    #[rocket::async_test]
+    async fn test_unlock_and_credit() {
+        let api = API::new_test_api_with_app_config(|c| c.admin_token = Some(TOKEN.to_string())).await;
+        let client = api.test_rocket_client().await;
+        assert_eq!(post_transaction(&client, model::RecordType::Deposit, 1, 10).await, Status::Ok);
+        assert_eq!(post_transaction(&client, model::RecordType::Dispute, 1, 0).await, Status::Ok);
+        assert_eq!(post_transaction(&client, model::RecordType::Chargeback, 1, 0).await, Status::Ok);
+        assert_eq!(post_transaction(&client, model::RecordType::Deposit, 2, 5).await, Status::Forbidden);
+
+        // adjustments can't go through the regular transaction endpoint
+        let response = client
+            .post(rocket::uri!("/transactions", transactions::post_transaction()))
+            .header(ContentType::JSON)
+            .body(r#"{"type": "unlock", "client": 1, "tx": 3, "amount": 0}"#)
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+        assert_eq!(response.into_json::<ErrorResponse>().await.unwrap().code, "admin_only");
+
+        assert_eq!(post_adjustment(&client, "wrong", model::RecordType::Unlock, 3, 0).await, Status::Unauthorized);
+        assert_eq!(post_adjustment(&client, TOKEN, model::RecordType::Unlock, 3, 1).await, Status::BadRequest);
+        assert_eq!(post_adjustment(&client, TOKEN, model::RecordType::Unlock, 3, 0).await, Status::Ok);
+        assert_eq!(post_adjustment(&client, TOKEN, model::RecordType::Credit, 4, 5).await, Status::Ok);
+        assert_eq!(
+            post_adjustment(&client, TOKEN, model::RecordType::Debit, 5, 10).await,
+            Status::UnprocessableEntity
+        );
+        assert_eq!(post_transaction(&client, model::RecordType::Deposit, 2, 5).await, Status::Ok);
+
+        let response = client.get("/clients/1").dispatch().await;
+        let client_response = response.into_json::<ClientResponse>().await.unwrap();
+        assert_eq!((client_response.available, client_response.locked), (model::Amount::from(10), false));
+
+        let response = client
+            .get(rocket::uri!("/admin", get_adjustment(4)))
+            .header(Header::new("Authorization", format!("Bearer {TOKEN:}")))
+            .dispatch()
+            .await;
+        let adjustment = response.into_json::<AdjustmentResponse>().await.unwrap();
+        assert_eq!(
+            (adjustment.record_type, adjustment.amount, adjustment.reason.as_str()),
+            (model::RecordType::Credit, model::Amount::from(5), "support ticket")
+        );
+
+        let response = client.get("/clients/1/transactions?type=unlock&type=credit").dispatch().await;
+        let page = response.into_json::<api::TransactionPageResponse>().await.unwrap();
+        assert_eq!(page.transactions.iter().map(|t| t.tx).collect::<Vec<_>>(), vec![3, 4]);
+
+        // adjustment tx ids don't conflict with those of regular transactions
+        assert_eq!(post_transaction(&client, model::RecordType::Deposit, 4, 1).await, Status::Ok);
+        assert_eq!(post_adjustment(&client, TOKEN, model::RecordType::Credit, 1, 2).await, Status::Ok);
+        assert_eq!(post_adjustment(&client, TOKEN, model::RecordType::Credit, 4, 5).await, Status::Ok);
+        assert_eq!(post_adjustment(&client, TOKEN, model::RecordType::Credit, 4, 6).await, Status::Conflict);
+        let sess = api.state.app.new_session(API::test_logger());
+        assert!(sess.get_duplicate_transactions_by_tx_id(4).await.unwrap().is_empty());
+        let response = client.get("/clients/1").dispatch().await;
+        let client_response = response.into_json::<ClientResponse>().await.unwrap();
+        assert_eq!(client_response.available, model::Amount::from(13));
+    }
+
+    #[rocket::async_test]
+    async fn test_invalid_adjustment() {
+        let api = API::new_test_api_with_app_config(|c| c.admin_token = Some(TOKEN.to_string())).await;
+        let client = api.test_rocket_client().await;
+        assert_eq!(post_transaction(&client, model::RecordType::Deposit, 1, 10).await, Status::Ok);
+
+        for (body, code) in [
+            (
+                r#"{"type": "deposit", "client": 1, "tx": 2, "amount": 5, "reason": "support ticket"}"#,
+                "invalid_adjustment_type",
+            ),
+            (r#"{"type": "credit", "client": 1, "tx": 2, "amount": 5, "reason": " "}"#, "missing_reason"),
+        ] {
+            let response = client
+                .post(rocket::uri!("/admin", super::post_adjustment()))
+                .header(ContentType::JSON)
+                .header(Header::new("Authorization", format!("Bearer {TOKEN:}")))
+                .body(body)
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::BadRequest);
+            assert_eq!(response.into_json::<ErrorResponse>().await.unwrap().code, code);
+        }
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"let-me-in", b"let-me-in"));
+        assert!(!constant_time_eq(b"let-me-in", b"let-me-io"));
+        assert!(!constant_time_eq(b"let-me-in", b"let-me"));
+    }
+
+    #[rocket::async_test]
+    async fn test_verify() {
+        let api = API::new_test_api_with_app_config(|c| c.admin_token = Some(TOKEN.to_string())).await;
+        let client = api.test_rocket_client().await;
+        assert_eq!(post_transaction(&client, model::RecordType::Deposit, 1, 10).await, Status::Ok);
+        assert_eq!(post_transaction(&client, model::RecordType::Deposit, 2, 5).await, Status::Ok);
+        assert_eq!(post_transaction(&client, model::RecordType::Dispute, 1, 0).await, Status::Ok);
+        assert_eq!(post_transaction(&client, model::RecordType::Dispute, 2, 0).await, Status::Ok);
+        assert_eq!(post_transaction(&client, model::RecordType::Resolve, 2, 0).await, Status::Ok);
+        assert_eq!(post_adjustment(&client, TOKEN, model::RecordType::Credit, 3, 1).await, Status::Ok);
+
+        let verify = |uri: String| {
+            let request = client.get(uri).header(Header::new("Authorization", format!("Bearer {TOKEN:}")));
+            async move { request.dispatch().await.into_json::<Vec<DiscrepancyResponse>>().await.unwrap() }
+        };
+        assert_eq!(verify(rocket::uri!("/admin", verify_all()).to_string()).await, vec![]);
+
+        // corrupt the stored account behind the engine's back
+        let sess = api.state.app.new_session(API::test_logger());
+        let stored = sess.get_client_by_id(1, model::Currency::USD).await.unwrap().unwrap();
+        let corrupted = stored
+            .with_patch(model::ClientPatch {
+                available: Some(model::Amount::from(7)),
+                ..Default::default()
+            })
+            .unwrap();
+        sess.store.add_client_revision(&corrupted).await.unwrap();
+
+        let discrepancies = verify(rocket::uri!("/admin", verify_client(1)).to_string()).await;
+        assert_eq!(
+            discrepancies.iter().map(|d| d.kind).collect::<Vec<_>>(),
+            vec![app::DiscrepancyKind::AvailableMismatch, app::DiscrepancyKind::SnapshotMismatch]
+        );
+        assert_eq!((discrepancies[0].stored.as_str(), discrepancies[0].expected.as_str()), ("7.0000", "6.0000"));
+
+        // the event log still has the real balances, which the snapshot can be rebuilt from
+        let response = client
+            .post(rocket::uri!("/admin", rebuild_client(1, _)))
+            .header(Header::new("Authorization", format!("Bearer {TOKEN:}")))
+            .dispatch()
+            .await;
+        let rebuilt = response.into_json::<ClientResponse>().await.unwrap();
+        assert_eq!((rebuilt.available, rebuilt.held), (model::Amount::from(6), model::Amount::from(10)));
+        assert_eq!(verify(rocket::uri!("/admin", verify_all()).to_string()).await, vec![]);
+
+        // the rebuilt account takes a new revision, so transactions can follow it
+        assert_eq!(post_transaction(&client, model::RecordType::Deposit, 4, 2).await, Status::Ok);
+        let response = client.get("/clients/1").dispatch().await;
+        let client_response = response.into_json::<ClientResponse>().await.unwrap();
+        assert_eq!(client_response.available, model::Amount::from(8));
+        assert_eq!(verify(rocket::uri!("/admin", verify_all()).to_string()).await, vec![]);
+        let response = client
+            .get(rocket::uri!("/admin", verify_client(2)))
+            .header(Header::new("Authorization", format!("Bearer {TOKEN:}")))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn test_verify_legacy() {
+        let api = API::new_test_api_with_app_config(|c| c.admin_token = Some(TOKEN.to_string())).await;
+        let client = api.test_rocket_client().await;
+        let sess = api.state.app.new_session(API::test_logger());
+        let deposit = |tx, amount| {
+            model::Transaction::from(transactions::PostTransaction {
+                tx,
+                client: 1,
+                record_type: model::RecordType::Deposit,
+                amount: model::Amount::from(amount),
+                currency: model::Currency::USD,
+                to_currency: None,
+            })
+        };
+        // tx 1 is still disputed and holds its amount, tx 2's dispute was closed
+        let legacy = model::Client::new(1, model::Currency::USD, Some(model::Amount::from(5)))
+            .with_patch(model::ClientPatch {
+                held: Some(model::Amount::from(10)),
+                ..Default::default()
+            })
+            .unwrap();
+        sess.store
+            .add_legacy_client(&legacy, &[deposit(1, 10), deposit(2, 5)], &[(1, false), (2, true)])
+            .await
+            .unwrap();
+
+        let response = client
+            .get(rocket::uri!("/admin", verify_client(1)))
+            .header(Header::new("Authorization", format!("Bearer {TOKEN:}")))
+            .dispatch()
+            .await;
+        assert_eq!(response.into_json::<Vec<DiscrepancyResponse>>().await.unwrap(), vec![]);
+    }
+
+    #[rocket::async_test]
+    async fn test_trial_balance() {
+        let api = API::new_test_api_with_app_config(|c| c.admin_token = Some(TOKEN.to_string())).await;
+        let client = api.test_rocket_client().await;
+        assert_eq!(post_transaction(&client, model::RecordType::Deposit, 1, 10).await, Status::Ok);
+        assert_eq!(post_transaction(&client, model::RecordType::Withdrawal, 2, 4).await, Status::Ok);
+        assert_eq!(post_transaction(&client, model::RecordType::Dispute, 1, 0).await, Status::Ok);
+
+        let response = client
+            .get(rocket::uri!("/admin", get_trial_balance()))
+            .header(Header::new("Authorization", format!("Bearer {TOKEN:}")))
+            .dispatch()
+            .await;
+        let trial_balance = response.into_json::<TrialBalanceResponse>().await.unwrap();
+        assert!(trial_balance.balanced);
+        assert_eq!(
+            trial_balance
+                .accounts
+                .iter()
+                .map(|a| (a.account.as_str(), a.debit, a.credit))
+                .collect::<Vec<_>>(),
+            vec![
+                ("client:1:available:USD", model::Amount::from(14), model::Amount::from(10)),
+                ("client:1:held:USD", model::Amount::ZERO, model::Amount::from(10)),
+                ("settlement:USD", model::Amount::from(10), model::Amount::from(4)),
+            ]
+        );
+        assert_eq!(
+            trial_balance.totals,
+            vec![CurrencyTotalResponse {
+                currency: model::Currency::USD,
+                debit: model::Amount::from(24),
+                credit: model::Amount::from(24),
+            }]
+        );
+        assert_eq!(trial_balance.mismatches, vec![]);
+
+        // a client changed outside of the journal no longer matches its ledger accounts
+        let sess = api.state.app.new_session(API::test_logger());
+        let stored = sess.get_client_by_id(1, model::Currency::USD).await.unwrap().unwrap();
+        let corrupted = stored
+            .with_patch(model::ClientPatch {
+                available: Some(model::Amount::from(5)),
+                ..Default::default()
+            })
+            .unwrap();
+        sess.store.add_client_revision(&corrupted).await.unwrap();
+        let response = client
+            .get(rocket::uri!("/admin", get_trial_balance()))
+            .header(Header::new("Authorization", format!("Bearer {TOKEN:}")))
+            .dispatch()
+            .await;
+        let trial_balance = response.into_json::<TrialBalanceResponse>().await.unwrap();
+        assert!(!trial_balance.balanced);
+        assert_eq!(
+            trial_balance.mismatches,
+            vec![LedgerMismatchResponse {
+                account: "client:1:available:USD".to_string(),
+                ledger: model::Amount::from(-4),
+                stored: model::Amount::from(5),
+            }]
+        );
+    }
+}
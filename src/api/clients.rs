@@ -1,9 +1,14 @@
 use crate::{
-    api::{session::Context, transactions::TransactionResponse, Route},
-    app::SanitizedError,
+    api::{
+        next_page_link, parse_page_query, parse_param, parse_params, session::Context, transactions::TransactionResponse, DisputeResponse, Route,
+        DEFAULT_PAGE_LIMIT, MAX_PAGE_LIMIT,
+    },
+    app::{self, SanitizedError},
     model,
 };
+use chrono::{DateTime, Utc};
 use rocket::fairing::AdHoc;
+use rocket::form::FromForm;
 use rocket::serde::{json::Json, Deserialize, Serialize};
 
 const CLIENT_ROUTE_BASE: &str = "/clients";
@@ -12,7 +17,18 @@ pub struct ClientRoute {}
 impl Route for ClientRoute {
     fn stage() -> AdHoc {
         AdHoc::on_ignite("Client Routing", |rocket| async {
-            rocket.mount(CLIENT_ROUTE_BASE, rocket::routes![get_client, get_all_clients])
+            rocket.mount(
+                CLIENT_ROUTE_BASE,
+                rocket::routes![
+                    get_client,
+                    get_client_accounts,
+                    get_client_disputes,
+                    get_client_transactions,
+                    get_client_revisions,
+                    get_client_revision,
+                    get_all_clients
+                ],
+            )
         })
     }
 }
@@ -22,20 +38,24 @@ impl Route for ClientRoute {
 #[serde(rename_all = "camelCase")]
 pub struct ClientResponse {
     pub id: u16,
-    pub available: f32,
-    pub held: f32,
-    pub total: f32,
+    pub currency: model::Currency,
+    pub available: model::Amount,
+    pub held: model::Amount,
+    pub total: model::Amount,
     pub locked: bool,
+    pub fees: model::Amount,
 }
 
 impl From<model::Client> for ClientResponse {
     fn from(c: model::Client) -> Self {
         return Self {
             id: c.id,
+            currency: c.currency,
             available: c.available,
             held: c.held,
             total: c.total,
             locked: c.locked,
+            fees: c.fees,
         };
     }
 }
@@ -44,47 +64,241 @@ impl From<ClientResponse> for model::CSVClient {
     fn from(c: ClientResponse) -> Self {
         return Self {
             client: c.id,
+            currency: c.currency,
             available: c.available,
             held: c.held,
             total: c.total,
             locked: c.locked,
+            fees: c.fees,
         };
     }
 }
 
-#[rocket::get("/<id>")]
-pub async fn get_client(id: u16, context: Context) -> Result<Json<ClientResponse>, SanitizedError> {
+#[derive(Debug, FromForm)]
+pub struct ClientQuery {
+    // returns the client as it was at this time instead of its current state
+    #[field(name = "asOf")]
+    pub as_of: Option<String>,
+    // the currency of the account to return, the default currency if unset
+    pub currency: Option<String>,
+}
+
+#[rocket::get("/<id>?<query..>")]
+pub async fn get_client(id: u16, query: ClientQuery, context: Context) -> Result<Json<ClientResponse>, SanitizedError> {
     let sess = context.session();
-    if let Some(client) = sess.get_client_by_id(id).await? {
+    let currency = parse_param("currency", &query.currency)?.unwrap_or_default();
+    let client = match parse_param("asOf", &query.as_of)? {
+        Some(as_of) => sess.get_client_as_of(id, currency, as_of).await?,
+        None => sess.get_client_by_id(id, currency).await?,
+    };
+    if let Some(client) = client {
         return Ok(Json(client.into()));
     } else {
+        return Err(SanitizedError::NotFound(format!("client {id:} has no {currency:} account")));
+    }
+}
+
+// Returns every account of a client, one per currency.
+#[rocket::get("/<id>/accounts")]
+pub async fn get_client_accounts(id: u16, context: Context) -> Result<Json<Vec<ClientResponse>>, SanitizedError> {
+    let sess = context.session();
+    let accounts = sess.get_client_accounts(id).await?;
+    if accounts.is_empty() {
         return Err(SanitizedError::NotFound(format!("client {id:} not found")));
     }
+    return Ok(Json(accounts.into_iter().map(ClientResponse::from).collect()));
 }
 
-#[rocket::get("/<id>/transactions")]
-pub async fn get_client_transactions(id: u16, context: Context) -> Result<Json<Vec<TransactionResponse>>, SanitizedError> {
+#[derive(Debug, FromForm)]
+pub struct ClientDisputesQuery {
+    // also returns the disputes that have been resolved or charged back
+    #[field(name = "includeClosed")]
+    pub include_closed: Option<String>,
+}
+
+#[rocket::get("/<id>/disputes?<query..>")]
+pub async fn get_client_disputes(id: u16, query: ClientDisputesQuery, context: Context) -> Result<Json<Vec<DisputeResponse>>, SanitizedError> {
     let sess = context.session();
-    let transactions: Vec<TransactionResponse> = sess
-        .get_all_client_transactions(id)
-        .await?
-        .into_iter()
-        .map(|x| TransactionResponse::from(x))
-        .collect();
-    return Ok(Json(transactions.into()));
+    let include_closed = parse_param("includeClosed", &query.include_closed)?.unwrap_or(false);
+    let disputes = sess.get_client_disputes(id, include_closed).await?;
+    return Ok(Json(disputes.into_iter().map(DisputeResponse::from).collect()));
+}
+
+#[derive(Debug, FromForm)]
+pub struct ClientTransactionsQuery {
+    pub limit: Option<String>,
+    pub after: Option<String>,
+    pub before: Option<String>,
+    pub order: Option<String>,
+    #[field(name = "type")]
+    pub record_types: Vec<String>,
 }
 
-#[rocket::get("/")]
-pub async fn get_all_clients(context: Context) -> Result<Json<Vec<ClientResponse>>, SanitizedError> {
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionPageResponse {
+    pub transactions: Vec<TransactionResponse>,
+    // a link to the next page, if there may be one
+    pub next: Option<String>,
+}
+
+#[rocket::get("/<id>/transactions?<query..>")]
+pub async fn get_client_transactions(id: u16, query: ClientTransactionsQuery, context: Context) -> Result<Json<TransactionPageResponse>, SanitizedError> {
     let sess = context.session();
-    let clients: Vec<ClientResponse> = sess.get_all_clients().await?.into_iter().map(|x| ClientResponse::from(x)).collect();
-    return Ok(Json(clients.into()));
+    let page_query = parse_page_query(&query.limit, &query.after, &query.before, &query.order)?;
+    let record_types: Vec<model::RecordType> = parse_params("type", &query.record_types)?;
+    let page = sess.get_client_transactions(id, &page_query, &record_types).await?;
+
+    let params: Vec<(&str, String)> = record_types.iter().map(|t| ("type", t.to_string())).collect();
+    return Ok(Json(TransactionPageResponse {
+        transactions: page.items.into_iter().map(TransactionResponse::from).collect(),
+        next: page
+            .next
+            .map(|cursor| next_page_link(&format!("{CLIENT_ROUTE_BASE}/{id}/transactions"), &page_query, cursor, &params)),
+    }));
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "camelCase")]
+pub struct ClientRevisionResponse {
+    pub revision_number: u32,
+    pub revision_time: DateTime<Utc>,
+    #[serde(flatten)]
+    pub client: ClientResponse,
+}
+
+impl From<model::Client> for ClientRevisionResponse {
+    fn from(c: model::Client) -> Self {
+        return Self {
+            revision_number: c.revision_number,
+            revision_time: c.revision_time,
+            client: c.into(),
+        };
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub struct ClientRevisionsQuery {
+    pub limit: Option<String>,
+    pub after: Option<String>,
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "camelCase")]
+pub struct ClientRevisionPageResponse {
+    pub revisions: Vec<ClientRevisionResponse>,
+    // a link to the next page, if there may be one
+    pub next: Option<String>,
+}
+
+#[rocket::get("/<id>/revisions?<query..>")]
+pub async fn get_client_revisions(id: u16, query: ClientRevisionsQuery, context: Context) -> Result<Json<ClientRevisionPageResponse>, SanitizedError> {
+    let sess = context.session();
+    let limit = parse_param("limit", &query.limit)?.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT);
+    let after = parse_param("after", &query.after)?.unwrap_or(0);
+    let currency: Option<model::Currency> = parse_param("currency", &query.currency)?;
+    let revisions = match sess.get_client_revisions(id, currency.unwrap_or_default(), after, limit).await? {
+        Some(revisions) => revisions,
+        None => return Err(SanitizedError::NotFound(format!("client {id:} not found"))),
+    };
+
+    let currency_param = currency.map(|c| format!("&currency={c}")).unwrap_or_default();
+    let next = match revisions.last() {
+        Some(last) if revisions.len() == limit => Some(format!(
+            "{CLIENT_ROUTE_BASE}/{id}/revisions?limit={limit}&after={}{currency_param}",
+            last.revision_number
+        )),
+        _ => None,
+    };
+    return Ok(Json(ClientRevisionPageResponse {
+        revisions: revisions.into_iter().map(ClientRevisionResponse::from).collect(),
+        next,
+    }));
+}
+
+#[rocket::get("/<id>/revisions/<revision_number>?<currency>")]
+pub async fn get_client_revision(
+    id: u16,
+    revision_number: u32,
+    currency: Option<String>,
+    context: Context,
+) -> Result<Json<ClientRevisionResponse>, SanitizedError> {
+    let sess = context.session();
+    let currency = parse_param("currency", &currency)?.unwrap_or_default();
+    if let Some(client) = sess.get_client_revision(id, currency, revision_number).await? {
+        return Ok(Json(client.into()));
+    } else {
+        return Err(SanitizedError::NotFound(format!("revision {revision_number:} of client {id:} not found")));
+    }
+}
+
+#[derive(Debug, FromForm)]
+pub struct ClientsQuery {
+    pub limit: Option<String>,
+    pub after: Option<String>,
+    pub before: Option<String>,
+    pub order: Option<String>,
+    pub locked: Option<String>,
+    pub held: Option<String>,
+    #[field(name = "minTotal")]
+    pub min_total: Option<String>,
+    #[field(name = "maxTotal")]
+    pub max_total: Option<String>,
+    pub currency: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "camelCase")]
+pub struct ClientPageResponse {
+    pub clients: Vec<ClientResponse>,
+    // a link to the next page, if there may be one
+    pub next: Option<String>,
+}
+
+#[rocket::get("/?<query..>")]
+pub async fn get_all_clients(query: ClientsQuery, context: Context) -> Result<Json<ClientPageResponse>, SanitizedError> {
+    let sess = context.session();
+    let page_query = parse_page_query(&query.limit, &query.after, &query.before, &query.order)?;
+    let filter = app::ClientFilter {
+        locked: parse_param("locked", &query.locked)?,
+        held: parse_param("held", &query.held)?,
+        min_total: parse_param("minTotal", &query.min_total)?,
+        max_total: parse_param("maxTotal", &query.max_total)?,
+        currency: parse_param("currency", &query.currency)?,
+    };
+    let page = sess.get_clients(&page_query, &filter).await?;
+
+    let mut params = vec![];
+    if let Some(locked) = filter.locked {
+        params.push(("locked", locked.to_string()));
+    }
+    if let Some(held) = filter.held {
+        params.push(("held", held.to_string()));
+    }
+    if let Some(min_total) = filter.min_total {
+        params.push(("minTotal", min_total.to_string()));
+    }
+    if let Some(max_total) = filter.max_total {
+        params.push(("maxTotal", max_total.to_string()));
+    }
+    if let Some(currency) = filter.currency {
+        params.push(("currency", currency.to_string()));
+    }
+    return Ok(Json(ClientPageResponse {
+        clients: page.items.into_iter().map(ClientResponse::from).collect(),
+        next: page.next.map(|cursor| next_page_link(CLIENT_ROUTE_BASE, &page_query, cursor, &params)),
+    }));
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::api::{transactions, API};
+    use crate::api::{format_time_param, transactions, API};
     use rocket::http::{ContentType, Status};
     use rocket::serde::json;
 
@@ -92,7 +306,7 @@ mod test {
     async fn test_get_client_empty() {
         let api = API::new_test_api().await;
         let client = api.test_rocket_client().await;
-        let response = client.get(rocket::uri!("/clients", get_client(1))).dispatch().await;
+        let response = client.get("/clients/1").dispatch().await;
         assert_eq!(response.status(), Status::NotFound);
     }
 
@@ -104,7 +318,9 @@ mod test {
             tx: 1,
             client: 1,
             record_type: model::RecordType::Deposit,
-            amount: 10.0,
+            amount: model::Amount::from(10),
+            currency: model::Currency::USD,
+            to_currency: None,
         };
         let response = client
             .post(rocket::uri!("/transactions", transactions::post_transaction()))
@@ -114,21 +330,99 @@ mod test {
             .await;
         assert_eq!(response.status(), Status::Ok);
 
-        let response = client.get(rocket::uri!("/clients", get_client(1))).dispatch().await;
+        let response = client.get("/clients/1").dispatch().await;
         assert_eq!(response.status(), Status::Ok);
 
         let expected = ClientResponse {
             id: 1,
-            available: 10.0,
-            held: 0.0,
-            total: 10.0,
+            currency: model::Currency::USD,
+            available: model::Amount::from(10),
+            held: model::Amount::ZERO,
+            total: model::Amount::from(10),
             locked: false,
+            fees: model::Amount::ZERO,
         };
         let resp_client = response.into_json::<ClientResponse>().await.unwrap();
 
         assert_eq!(expected, resp_client);
     }
 
+    #[rocket::async_test]
+    async fn test_get_client_accounts() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        for (tx, currency) in [(1, "USD"), (2, "EUR"), (3, "EUR")] {
+            let deposit = transactions::PostTransaction {
+                tx,
+                client: 1,
+                record_type: model::RecordType::Deposit,
+                amount: model::Amount::from(10),
+                currency: currency.parse().unwrap(),
+                to_currency: None,
+            };
+            let response = client
+                .post(rocket::uri!("/transactions", transactions::post_transaction()))
+                .header(ContentType::JSON)
+                .body(json::to_string(&deposit).unwrap())
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
+        }
+
+        let response = client.get("/clients/1/accounts").dispatch().await;
+        let accounts = response.into_json::<Vec<ClientResponse>>().await.unwrap();
+        assert_eq!(
+            accounts.iter().map(|a| (a.currency.to_string(), a.total)).collect::<Vec<_>>(),
+            vec![("USD".to_string(), model::Amount::from(10)), ("EUR".to_string(), model::Amount::from(20))]
+        );
+
+        let response = client.get("/clients/1?currency=eur").dispatch().await;
+        assert_eq!(response.into_json::<ClientResponse>().await.unwrap().total, model::Amount::from(20));
+        let response = client.get("/clients/1?currency=GBP").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get("/clients/1?currency=euro").dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client.get("/clients/2/accounts").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn test_get_client_as_of() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        let mut times = vec![];
+        for i in 1..=3 {
+            let deposit = transactions::PostTransaction {
+                tx: i,
+                client: 1,
+                record_type: model::RecordType::Deposit,
+                amount: model::Amount::from(10),
+                currency: model::Currency::USD,
+                to_currency: None,
+            };
+            let response = client
+                .post(rocket::uri!("/transactions", transactions::post_transaction()))
+                .header(ContentType::JSON)
+                .body(json::to_string(&deposit).unwrap())
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
+            times.push(format_time_param(&Utc::now()));
+        }
+
+        for (i, time) in times.iter().enumerate() {
+            let response = client.get(format!("/clients/1?asOf={time}")).dispatch().await;
+            assert_eq!(response.status(), Status::Ok);
+            let resp_client = response.into_json::<ClientResponse>().await.unwrap();
+            assert_eq!(resp_client.total, model::Amount::from(10 * (i as i32 + 1)));
+        }
+
+        let response = client.get("/clients/1?asOf=2000-01-01T00:00:00Z").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get("/clients/1?asOf=yesterday").dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
     #[rocket::async_test]
     async fn test_get_all_client() {
         let api = API::new_test_api().await;
@@ -138,7 +432,102 @@ mod test {
                 tx: i,
                 client: i as u16,
                 record_type: model::RecordType::Deposit,
-                amount: 10.0,
+                amount: model::Amount::from(10),
+                currency: model::Currency::USD,
+                to_currency: None,
+            };
+            let response = client
+                .post(rocket::uri!("/transactions", transactions::post_transaction()))
+                .header(ContentType::JSON)
+                .body(json::to_string(&deposit).unwrap())
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
+        }
+
+        let response = client.get("/clients").dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let resp_client = response.into_json::<ClientPageResponse>().await.unwrap();
+        assert_eq!(9, resp_client.clients.len());
+        assert_eq!(None, resp_client.next);
+    }
+
+    #[rocket::async_test]
+    async fn test_get_all_client_paginated() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        for i in 1..10 {
+            let deposit = transactions::PostTransaction {
+                tx: i,
+                client: i as u16,
+                record_type: model::RecordType::Deposit,
+                amount: model::Amount::from(i as i32),
+                currency: model::Currency::USD,
+                to_currency: None,
+            };
+            let response = client
+                .post(rocket::uri!("/transactions", transactions::post_transaction()))
+                .header(ContentType::JSON)
+                .body(json::to_string(&deposit).unwrap())
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
+        }
+        let dispute = transactions::PostTransaction {
+            tx: 2,
+            client: 2,
+            record_type: model::RecordType::Dispute,
+            amount: model::Amount::ZERO,
+            currency: model::Currency::USD,
+            to_currency: None,
+        };
+        let response = client
+            .post(rocket::uri!("/transactions", transactions::post_transaction()))
+            .header(ContentType::JSON)
+            .body(json::to_string(&dispute).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let mut next = Some("/clients?limit=2&minTotal=3&maxTotal=8.5".to_string());
+        let mut ids = vec![];
+        while let Some(link) = next {
+            let response = client.get(link).dispatch().await;
+            assert_eq!(response.status(), Status::Ok);
+            let page = response.into_json::<ClientPageResponse>().await.unwrap();
+            assert!(page.clients.len() <= 2);
+            ids.extend(page.clients.into_iter().map(|c| c.id));
+            next = page.next;
+        }
+        ids.sort();
+        assert_eq!(ids, vec![3, 4, 5, 6, 7, 8]);
+
+        let response = client.get("/clients?held=true").dispatch().await;
+        let page = response.into_json::<ClientPageResponse>().await.unwrap();
+        assert_eq!(page.clients.iter().map(|c| c.id).collect::<Vec<_>>(), vec![2]);
+
+        let response = client.get("/clients?locked=true").dispatch().await;
+        let page = response.into_json::<ClientPageResponse>().await.unwrap();
+        assert!(page.clients.is_empty());
+
+        let response = client.get("/clients?minTotal=abc").dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+
+    #[rocket::async_test]
+    async fn test_get_all_client_accounts_paginated() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        let currencies = [model::Currency::USD, "EUR".parse().unwrap(), "GBP".parse().unwrap()];
+        for (i, currency) in currencies.into_iter().enumerate() {
+            let deposit = transactions::PostTransaction {
+                tx: i as u32 + 1,
+                client: 1,
+                record_type: model::RecordType::Deposit,
+                amount: model::Amount::from(1),
+                currency,
+                to_currency: None,
             };
             let response = client
                 .post(rocket::uri!("/transactions", transactions::post_transaction()))
@@ -149,10 +538,120 @@ mod test {
             assert_eq!(response.status(), Status::Ok);
         }
 
-        let response = client.get(rocket::uri!("/clients", get_all_clients())).dispatch().await;
+        for order in ["asc", "desc"] {
+            let mut next = Some(format!("/clients?limit=1&order={order}&minTotal=0.5"));
+            let mut accounts = vec![];
+            while let Some(link) = next {
+                let response = client.get(link).dispatch().await;
+                assert_eq!(response.status(), Status::Ok);
+                let page = response.into_json::<ClientPageResponse>().await.unwrap();
+                accounts.extend(page.clients.into_iter().map(|c| c.currency.to_string()));
+                next = page.next;
+            }
+            accounts.sort();
+            assert_eq!(accounts, vec!["EUR", "GBP", "USD"]);
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_get_client_revisions() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        let response = client.get("/clients/1/revisions").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+
+        for (i, record_type) in [model::RecordType::Deposit, model::RecordType::Deposit, model::RecordType::Withdrawal]
+            .into_iter()
+            .enumerate()
+        {
+            let tx = transactions::PostTransaction {
+                tx: i as u32 + 1,
+                client: 1,
+                record_type,
+                amount: model::Amount::from(5),
+                currency: model::Currency::USD,
+                to_currency: None,
+            };
+            let response = client
+                .post(rocket::uri!("/transactions", transactions::post_transaction()))
+                .header(ContentType::JSON)
+                .body(json::to_string(&tx).unwrap())
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
+        }
+
+        // the client is created empty, then each transaction adds a revision
+        let mut next = Some("/clients/1/revisions?limit=3".to_string());
+        let mut revisions = vec![];
+        while let Some(link) = next {
+            let response = client.get(link).dispatch().await;
+            assert_eq!(response.status(), Status::Ok);
+            let page = response.into_json::<ClientRevisionPageResponse>().await.unwrap();
+            revisions.extend(page.revisions);
+            next = page.next;
+        }
+        assert_eq!(revisions.iter().map(|r| r.revision_number).collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert_eq!(
+            revisions.iter().map(|r| r.client.available).collect::<Vec<_>>(),
+            vec![model::Amount::ZERO, model::Amount::from(5), model::Amount::from(10), model::Amount::from(5)]
+        );
+
+        let response = client.get(rocket::uri!("/clients", get_client_revision(1, 3, _))).dispatch().await;
         assert_eq!(response.status(), Status::Ok);
+        let revision = response.into_json::<ClientRevisionResponse>().await.unwrap();
+        assert_eq!(revision.client.total, model::Amount::from(10));
+
+        let response = client.get(rocket::uri!("/clients", get_client_revision(1, 5, _))).dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn test_get_client_transactions() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        for i in 1..=6 {
+            let tx = transactions::PostTransaction {
+                tx: i,
+                client: if i == 6 { 2 } else { 1 },
+                record_type: if i % 2 == 0 && i != 6 {
+                    model::RecordType::Withdrawal
+                } else {
+                    model::RecordType::Deposit
+                },
+                amount: model::Amount::from(1),
+                currency: model::Currency::USD,
+                to_currency: None,
+            };
+            let response = client
+                .post(rocket::uri!("/transactions", transactions::post_transaction()))
+                .header(ContentType::JSON)
+                .body(json::to_string(&tx).unwrap())
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
+        }
+
+        let mut next = Some("/clients/1/transactions?limit=2".to_string());
+        let mut txs = vec![];
+        while let Some(link) = next {
+            let response = client.get(link).dispatch().await;
+            assert_eq!(response.status(), Status::Ok);
+            let page = response.into_json::<TransactionPageResponse>().await.unwrap();
+            assert!(page.transactions.len() <= 2);
+            txs.extend(page.transactions.into_iter().map(|t| t.tx));
+            next = page.next;
+        }
+        assert_eq!(txs, vec![1, 2, 3, 4, 5]);
+
+        let response = client.get("/clients/1/transactions?order=desc&type=withdrawal&limit=1").dispatch().await;
+        let page = response.into_json::<TransactionPageResponse>().await.unwrap();
+        assert_eq!(page.transactions.iter().map(|t| t.tx).collect::<Vec<_>>(), vec![4]);
+        let response = client.get(page.next.unwrap()).dispatch().await;
+        let page = response.into_json::<TransactionPageResponse>().await.unwrap();
+        assert_eq!(page.transactions.iter().map(|t| t.tx).collect::<Vec<_>>(), vec![2]);
 
-        let resp_client = response.into_json::<Vec<ClientResponse>>().await.unwrap();
-        assert_eq!(9, resp_client.len());
+        let response = client.get("/clients/1/transactions?type=unknown").dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
     }
 }
@@ -0,0 +1,268 @@
+use crate::{
+    api::{session::Context, Route},
+    app::SanitizedError,
+    model,
+};
+use chrono::{DateTime, Utc};
+use rocket::fairing::AdHoc;
+use rocket::serde::{json::Json, Deserialize, Serialize};
+
+pub const DISPUTE_ROUTE_BASE: &str = "/disputes";
+pub struct DisputeRoute {}
+
+impl Route for DisputeRoute {
+    fn stage() -> AdHoc {
+        AdHoc::on_ignite("Dispute Routing", |rocket| async {
+            rocket.mount(DISPUTE_ROUTE_BASE, rocket::routes![get_dispute, get_dispute_revisions])
+        })
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "camelCase")]
+pub struct DisputeResponse {
+    pub id: String,
+    pub client: u16,
+    // the disputed tx
+    pub tx: u32,
+    #[serde(rename = "type")]
+    pub record_type: model::RecordType,
+    pub amount: model::Amount,
+    pub currency: model::Currency,
+    // how many times the tx has been disputed, this dispute included
+    pub dispute_number: u32,
+    pub held_amount: model::Amount,
+    pub status: model::DisputeStatus,
+    // the type of the record that closed the dispute, and when it did
+    pub resolved_by: Option<model::RecordType>,
+    pub resolution_time: Option<DateTime<Utc>>,
+
+    pub creation_time: DateTime<Utc>,
+    pub revision_number: u32,
+    pub revision_time: DateTime<Utc>,
+}
+
+impl From<model::Dispute> for DisputeResponse {
+    fn from(d: model::Dispute) -> Self {
+        return Self {
+            id: d.id.to_string(),
+            client: d.referenced_tx.client,
+            tx: d.referenced_tx.tx,
+            record_type: d.referenced_tx.record_type,
+            amount: d.referenced_tx.amount,
+            currency: d.referenced_tx.currency,
+            dispute_number: d.dispute_number,
+            held_amount: d.held_amount,
+            status: d.status,
+            resolved_by: d.resolved_by_tx.map(|tx| tx.record_type),
+            resolution_time: d.resolution_time,
+            creation_time: d.creation_time,
+            revision_number: d.revision_number,
+            revision_time: d.revision_time,
+        };
+    }
+}
+
+#[rocket::get("/<id>")]
+pub async fn get_dispute(id: &str, context: Context) -> Result<Json<DisputeResponse>, SanitizedError> {
+    let sess = context.session();
+    let dispute_id: model::Id = id.parse().map_err(|_| SanitizedError::NotFound(format!("dispute {id:} not found")))?;
+    if let Some(dispute) = sess.get_dispute_by_id(&dispute_id).await? {
+        return Ok(Json(dispute.into()));
+    } else {
+        return Err(SanitizedError::NotFound(format!("dispute {id:} not found")));
+    }
+}
+
+// Returns the full lifecycle of a dispute, one revision per status change.
+#[rocket::get("/<id>/revisions")]
+pub async fn get_dispute_revisions(id: &str, context: Context) -> Result<Json<Vec<DisputeResponse>>, SanitizedError> {
+    let sess = context.session();
+    let dispute_id: model::Id = id.parse().map_err(|_| SanitizedError::NotFound(format!("dispute {id:} not found")))?;
+    if let Some(revisions) = sess.get_dispute_revisions(&dispute_id).await? {
+        return Ok(Json(revisions.into_iter().map(DisputeResponse::from).collect()));
+    } else {
+        return Err(SanitizedError::NotFound(format!("dispute {id:} not found")));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::api::{transactions, API};
+    use crate::app;
+    use rocket::http::{ContentType, Status};
+    use rocket::local::asynchronous::Client;
+    use rocket::serde::json;
+
+    async fn post_transaction(client: &Client, record_type: model::RecordType, tx: u32, amount: i32) {
+        let transaction = transactions::PostTransaction {
+            tx,
+            client: 1,
+            record_type,
+            amount: model::Amount::from(amount),
+            currency: model::Currency::USD,
+            to_currency: None,
+        };
+        let response = client
+            .post(rocket::uri!("/transactions", transactions::post_transaction()))
+            .header(ContentType::JSON)
+            .body(json::to_string(&transaction).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[rocket::async_test]
+    async fn test_get_disputes() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        post_transaction(&client, model::RecordType::Deposit, 1, 10).await;
+        post_transaction(&client, model::RecordType::Deposit, 2, 5).await;
+        post_transaction(&client, model::RecordType::Dispute, 1, 0).await;
+        post_transaction(&client, model::RecordType::Dispute, 2, 0).await;
+        post_transaction(&client, model::RecordType::Resolve, 2, 0).await;
+
+        let response = client
+            .get(rocket::uri!("/transactions", transactions::get_transaction_dispute(1)))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+        let dispute = response.into_json::<DisputeResponse>().await.unwrap();
+        assert_eq!((dispute.client, dispute.tx, dispute.amount), (1, 1, model::Amount::from(10)));
+        assert_eq!(dispute.status, model::DisputeStatus::Open);
+
+        let response = client.get(rocket::uri!("/disputes", get_dispute(&dispute.id))).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_json::<DisputeResponse>().await.unwrap(), dispute);
+
+        let response = client.get("/clients/1/disputes").dispatch().await;
+        let disputes = response.into_json::<Vec<DisputeResponse>>().await.unwrap();
+        assert_eq!(disputes.iter().map(|d| d.tx).collect::<Vec<_>>(), vec![1]);
+
+        let response = client.get("/clients/1/disputes?includeClosed=true").dispatch().await;
+        let mut disputes = response.into_json::<Vec<DisputeResponse>>().await.unwrap();
+        assert_eq!(
+            disputes.iter().map(|d| (d.tx, d.status)).collect::<Vec<_>>(),
+            vec![(1, model::DisputeStatus::Open), (2, model::DisputeStatus::Resolved)]
+        );
+
+        let dispute = disputes.pop().unwrap();
+        assert_eq!(dispute.resolved_by, Some(model::RecordType::Resolve));
+        assert!(dispute.resolution_time.is_some());
+        let response = client.get(rocket::uri!("/disputes", get_dispute_revisions(&dispute.id))).dispatch().await;
+        let revisions = response.into_json::<Vec<DisputeResponse>>().await.unwrap();
+        assert_eq!(
+            revisions.iter().map(|d| (d.revision_number, d.status)).collect::<Vec<_>>(),
+            vec![(1, model::DisputeStatus::Open), (2, model::DisputeStatus::Resolved)]
+        );
+
+        let response = client
+            .get(rocket::uri!("/transactions", transactions::get_transaction_dispute(3)))
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::NotFound);
+        let response = client.get(rocket::uri!("/disputes", get_dispute("not-an-id"))).dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[rocket::async_test]
+    async fn test_redispute() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        post_transaction(&client, model::RecordType::Deposit, 1, 10).await;
+        post_transaction(&client, model::RecordType::Dispute, 1, 0).await;
+        post_transaction(&client, model::RecordType::Resolve, 1, 0).await;
+        let transaction = transactions::PostTransaction {
+            tx: 1,
+            client: 1,
+            record_type: model::RecordType::Dispute,
+            amount: model::Amount::from(0),
+            currency: model::Currency::USD,
+            to_currency: None,
+        };
+        let response = client
+            .post(rocket::uri!("/transactions", transactions::post_transaction()))
+            .header(ContentType::JSON)
+            .body(json::to_string(&transaction).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let api = API::new_test_api_with_app_config(|c| c.redisputes = app::RedisputePolicy::AfterResolve).await;
+        let client = api.test_rocket_client().await;
+        post_transaction(&client, model::RecordType::Deposit, 1, 10).await;
+        post_transaction(&client, model::RecordType::Dispute, 1, 0).await;
+        post_transaction(&client, model::RecordType::Resolve, 1, 0).await;
+        post_transaction(&client, model::RecordType::Dispute, 1, 0).await;
+
+        let response = client
+            .get(rocket::uri!("/transactions", transactions::get_transaction_disputes(1)))
+            .dispatch()
+            .await;
+        let disputes = response.into_json::<Vec<DisputeResponse>>().await.unwrap();
+        assert_eq!(
+            disputes.iter().map(|d| (d.dispute_number, d.status)).collect::<Vec<_>>(),
+            vec![(1, model::DisputeStatus::Resolved), (2, model::DisputeStatus::Open)]
+        );
+    }
+
+    #[rocket::async_test]
+    async fn test_legacy_disputes() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        let sess = api.state.app.new_session(API::test_logger());
+        let deposit = |tx, amount| {
+            model::Transaction::from(transactions::PostTransaction {
+                tx,
+                client: 1,
+                record_type: model::RecordType::Deposit,
+                amount: model::Amount::from(amount),
+                currency: model::Currency::USD,
+                to_currency: None,
+            })
+        };
+        // tx 1 is still disputed, tx 2's dispute was closed
+        let legacy = model::Client::new(1, model::Currency::USD, Some(model::Amount::from(5)))
+            .with_patch(model::ClientPatch {
+                held: Some(model::Amount::from(10)),
+                ..Default::default()
+            })
+            .unwrap();
+        sess.store
+            .add_legacy_client(&legacy, &[deposit(1, 10), deposit(2, 5)], &[(1, false), (2, true)])
+            .await
+            .unwrap();
+
+        let response = client.get("/clients/1/disputes").dispatch().await;
+        let disputes = response.into_json::<Vec<DisputeResponse>>().await.unwrap();
+        assert_eq!(
+            disputes.iter().map(|d| (d.tx, d.status)).collect::<Vec<_>>(),
+            vec![(1, model::DisputeStatus::Open)]
+        );
+        let response = client.get("/clients/1/disputes?includeClosed=true").dispatch().await;
+        let disputes = response.into_json::<Vec<DisputeResponse>>().await.unwrap();
+        assert_eq!(
+            disputes.iter().map(|d| (d.tx, d.status)).collect::<Vec<_>>(),
+            vec![(1, model::DisputeStatus::Open), (2, model::DisputeStatus::Resolved)]
+        );
+
+        // disputes made after the upgrade are listed along with the legacy ones
+        post_transaction(&client, model::RecordType::Deposit, 3, 1).await;
+        post_transaction(&client, model::RecordType::Dispute, 3, 0).await;
+        post_transaction(&client, model::RecordType::Resolve, 3, 0).await;
+        let response = client.get("/clients/1/disputes?includeClosed=true").dispatch().await;
+        let disputes = response.into_json::<Vec<DisputeResponse>>().await.unwrap();
+        assert_eq!(disputes.iter().map(|d| d.tx).collect::<Vec<_>>(), vec![1, 2, 3]);
+
+        for tx in [1, 2] {
+            let response = client
+                .get(rocket::uri!("/transactions", transactions::get_transaction_disputes(tx)))
+                .dispatch()
+                .await;
+            let disputes = response.into_json::<Vec<DisputeResponse>>().await.unwrap();
+            assert_eq!(disputes.iter().map(|d| (d.tx, d.dispute_number)).collect::<Vec<_>>(), vec![(tx, 1)]);
+        }
+    }
+}
@@ -1,21 +1,96 @@
-use crate::app::SanitizedError;
-use rocket::http::{ContentType, Status};
+use crate::api::RequestId;
+use crate::app::{RejectionReason, SanitizedError};
+use rocket::http::Status;
 use rocket::request::Request;
 use rocket::response::{self, Responder, Response};
+use rocket::serde::{json::Json, Deserialize, Serialize};
+
+// The problem document returned as the body of every error response, including Rocket's catchers.
+// The code is stable and meant to be matched on by clients, the message is for humans.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorResponse {
+    pub status: u16,
+    pub code: String,
+    pub message: String,
+    pub request_id: String,
+}
+
+impl ErrorResponse {
+    pub fn new(req: &Request<'_>, status: Status, code: &str, message: String) -> Self {
+        return Self {
+            status: status.code,
+            code: code.to_string(),
+            message,
+            request_id: RequestId::of(req).to_string(),
+        };
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ErrorResponse {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
+        let status = Status::from_code(self.status).unwrap_or(Status::InternalServerError);
+        Response::build_from(Json(self).respond_to(req)?).status(status).ok()
+    }
+}
 
 impl<'r> Responder<'r, 'static> for SanitizedError {
     fn respond_to(self, req: &'r Request<'_>) -> response::Result<'static> {
-        let status_code = match self {
+        let status_code = match &self {
             SanitizedError::NotFound(_) => Status::NotFound,
             SanitizedError::Unauthorized => Status::Forbidden,
             SanitizedError::Contention | SanitizedError::InternalError => Status::InternalServerError,
-            SanitizedError::IncorrectRevisionNumber => Status::Conflict,
+            SanitizedError::IncorrectRevisionNumber | SanitizedError::TransactionIdConflict(_) => Status::Conflict,
+            SanitizedError::Rejected(reason) => rejection_status(reason),
             SanitizedError::UserError(_) => Status::BadRequest,
         };
 
-        Response::build_from(self.to_string().respond_to(req)?)
-            .status(status_code)
-            .header(ContentType::new("text", "plain"))
-            .ok()
+        ErrorResponse::new(req, status_code, self.code(), self.to_string()).respond_to(req)
     }
 }
+
+fn rejection_status(reason: &RejectionReason) -> Status {
+    match reason {
+        RejectionReason::ClientLocked | RejectionReason::ClientMismatch | RejectionReason::AdminOnly | RejectionReason::EngineOnly => Status::Forbidden,
+        RejectionReason::ReferencedTxNotFound => Status::NotFound,
+        RejectionReason::InvalidAmount
+        | RejectionReason::InvalidConversion
+        | RejectionReason::InvalidAdjustmentType
+        | RejectionReason::MissingReason
+        | RejectionReason::MalformedRecord => Status::BadRequest,
+        RejectionReason::InsufficientFunds
+        | RejectionReason::FxRateNotFound
+        | RejectionReason::FeeExceedsAmount
+        | RejectionReason::AmountOverflow
+        | RejectionReason::ReferencedTxNotDisputable
+        | RejectionReason::WithdrawalDisputeNotAllowed
+        | RejectionReason::RedisputeNotAllowed => Status::UnprocessableEntity,
+        RejectionReason::AlreadyDisputed | RejectionReason::NotDisputed | RejectionReason::DisputeClosed | RejectionReason::TransactionIdConflict => {
+            Status::Conflict
+        }
+        // the transaction didn't go through but may on a retry
+        RejectionReason::Contention => Status::ServiceUnavailable,
+    }
+}
+
+// Replaces Rocket's HTML error pages, e.g. for unknown routes or request bodies that aren't valid
+// JSON, with the same problem document the routes return.
+#[rocket::catch(default)]
+pub fn default_catcher(status: Status, req: &Request<'_>) -> ErrorResponse {
+    let code = match status.code {
+        400 => "bad_request",
+        401 => "unauthorized",
+        404 => "not_found",
+        422 => "unprocessable_entity",
+        500 => "internal_error",
+        _ if status.class().is_client_error() => "client_error",
+        _ => "server_error",
+    };
+    let message = match status.code {
+        404 => format!("No route matches {} {}.", req.method(), req.uri().path()),
+        400 | 422 => "The request could not be parsed.".to_string(),
+        _ => status.reason_lossy().to_string(),
+    };
+    return ErrorResponse::new(req, status, code, message);
+}
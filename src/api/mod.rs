@@ -4,12 +4,20 @@ use std::sync::Arc;
 
 pub mod error;
 pub use error::*;
+pub mod query;
+pub use query::*;
+pub mod request_id;
+pub use request_id::*;
 pub mod session;
 pub use session::*;
 pub mod clients;
 pub use clients::*;
 pub mod transactions;
 pub use transactions::*;
+pub mod disputes;
+pub use disputes::*;
+pub mod admin;
+pub use admin::*;
 
 #[rocket::get("/healthz")]
 fn healthz() -> Result<String, SanitizedError> {
@@ -58,8 +66,12 @@ impl API {
         let r = rocket::custom(provider)
             .manage(self.state.clone())
             .mount("/", rocket::routes![healthz, index])
+            .register("/", rocket::catchers![default_catcher])
+            .attach(RequestId::fairing())
             .attach(ClientRoute::stage())
-            .attach(TransactionRoute::stage());
+            .attach(TransactionRoute::stage())
+            .attach(DisputeRoute::stage())
+            .attach(AdminRoute::stage());
 
         return Ok(r);
     }
@@ -108,7 +120,7 @@ impl API {
 #[cfg(test)]
 mod test {
     use super::*;
-    use rocket::http::Status;
+    use rocket::http::{ContentType, Header, Status};
 
     #[rocket::async_test]
     async fn test_healthz() {
@@ -124,4 +136,59 @@ mod test {
         assert_eq!(s1, s2);
         assert_eq!(s1.unwrap(), "OK".to_string());
     }
+
+    #[rocket::async_test]
+    async fn test_unknown_route() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        let response = client.get("/does-not-exist").dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+
+        let request_id = response.headers().get_one(REQUEST_ID_HEADER).unwrap().to_string();
+        let error = response.into_json::<ErrorResponse>().await.unwrap();
+        assert_eq!(error.status, 404);
+        assert_eq!(error.code, "not_found");
+        assert_eq!(error.request_id, request_id);
+    }
+
+    #[rocket::async_test]
+    async fn test_malformed_json() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        for body in ["{not json", r#"{"tx": 1}"#] {
+            let response = client
+                .post(rocket::uri!("/transactions", transactions::post_transaction()))
+                .header(ContentType::JSON)
+                .header(Header::new(REQUEST_ID_HEADER, "abc123"))
+                .body(body)
+                .dispatch()
+                .await;
+            assert!(response.status().class().is_client_error());
+            assert_eq!(response.headers().get_one(REQUEST_ID_HEADER), Some("abc123"));
+
+            let status = response.status().code;
+            let error = response.into_json::<ErrorResponse>().await.unwrap();
+            assert_eq!(error.status, status);
+            assert_eq!(error.request_id, "abc123");
+        }
+    }
+
+    #[rocket::async_test]
+    async fn test_invalid_request_id() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        let too_long = "a".repeat(65);
+        for request_id in ["abc 123", "abc\u{7f}", "abc;def", "ünïcode", too_long.as_str()] {
+            let response = client
+                .get("/does-not-exist")
+                .header(Header::new(REQUEST_ID_HEADER, request_id.to_string()))
+                .dispatch()
+                .await;
+            let generated = response.headers().get_one(REQUEST_ID_HEADER).unwrap().to_string();
+            assert_ne!(generated, request_id);
+            assert_eq!(generated.len(), 16);
+            assert_eq!(response.into_json::<ErrorResponse>().await.unwrap().request_id, generated);
+        }
+    }
 }
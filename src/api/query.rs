@@ -0,0 +1,79 @@
+use crate::app::{self, SanitizedError};
+use chrono::{DateTime, SecondsFormat, Utc};
+use rocket::http::RawStr;
+use std::{fmt::Display, str::FromStr};
+
+// The default and maximum number of items returned by paginated routes.
+pub const DEFAULT_PAGE_LIMIT: usize = 100;
+pub const MAX_PAGE_LIMIT: usize = 1000;
+
+// Parses an optional query parameter. Rocket silently ignores optional parameters that fail to
+// parse, so they are taken as strings and parsed here to report them as a bad request instead.
+pub fn parse_param<T: FromStr>(name: &str, value: &Option<String>) -> Result<Option<T>, SanitizedError>
+where
+    T::Err: Display,
+{
+    value.as_deref().map(|v| parse_value(name, v)).transpose()
+}
+
+pub fn parse_params<T: FromStr>(name: &str, values: &[String]) -> Result<Vec<T>, SanitizedError>
+where
+    T::Err: Display,
+{
+    values.iter().map(|v| parse_value(name, v)).collect()
+}
+
+fn parse_value<T: FromStr>(name: &str, value: &str) -> Result<T, SanitizedError>
+where
+    T::Err: Display,
+{
+    value
+        .parse()
+        .map_err(|err| SanitizedError::UserError(format!("Invalid {name} parameter: {err}")))
+}
+
+// Parses the limit, after, before and order parameters shared by paginated routes.
+pub fn parse_page_query(
+    limit: &Option<String>,
+    after: &Option<String>,
+    before: &Option<String>,
+    order: &Option<String>,
+) -> Result<app::PageQuery, SanitizedError> {
+    return Ok(app::PageQuery {
+        after: parse_param("after", after)?,
+        before: parse_param("before", before)?,
+        limit: parse_param("limit", limit)?.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT),
+        order: parse_param("order", order)?.unwrap_or_default(),
+    });
+}
+
+// Formats a time as UTC with a Z suffix, so that it can be used in a query string as is.
+pub fn format_time_param(t: &DateTime<Utc>) -> String {
+    t.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+// Builds the link to the page following the one selected by page, given the cursor it returned.
+// params are the route's other query parameters, which are carried over. Every value is percent
+// encoded.
+pub fn next_page_link(path: &str, page: &app::PageQuery, cursor: app::PageCursor, params: &[(&str, String)]) -> String {
+    let (after, before) = match page.order {
+        app::SortOrder::Ascending => (Some(cursor), page.before.clone()),
+        app::SortOrder::Descending => (page.after.clone(), Some(cursor)),
+    };
+
+    let mut link = format!("{path}?limit={}&order={}", page.limit, page.order);
+    if let Some(after) = after {
+        link += &format!("&after={}", encode_param(&after.to_string()));
+    }
+    if let Some(before) = before {
+        link += &format!("&before={}", encode_param(&before.to_string()));
+    }
+    for (name, value) in params {
+        link += &format!("&{name}={}", encode_param(value));
+    }
+    return link;
+}
+
+fn encode_param(value: &str) -> String {
+    RawStr::new(value).percent_encode().to_string()
+}
@@ -0,0 +1,42 @@
+use rocket::{fairing::AdHoc, Request};
+use std::fmt;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+// The longest id given by a caller that is reused.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+// Identifies a request in error bodies, logs and the X-Request-Id response header. An id given by
+// the caller in the X-Request-Id header is reused if it is valid, otherwise a random one is
+// generated.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub fn of<'r>(req: &'r Request<'_>) -> &'r RequestId {
+        req.local_cache(|| match req.headers().get_one(REQUEST_ID_HEADER) {
+            Some(id) if is_valid(id) => RequestId(id.to_string()),
+            _ => RequestId(format!("{:016x}", rand::random::<u64>())),
+        })
+    }
+
+    pub fn fairing() -> AdHoc {
+        AdHoc::on_response("Request Id", |req, res| {
+            Box::pin(async move {
+                res.set_raw_header(REQUEST_ID_HEADER, RequestId::of(req).0.clone());
+            })
+        })
+    }
+}
+
+// Only short ids of ASCII letters, digits, '-', '_' and '.' are reused, so that a caller can't
+// inject anything into logs or response headers.
+fn is_valid(id: &str) -> bool {
+    return !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && id.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b));
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -34,8 +34,8 @@ impl<'r> FromRequest<'r> for Context {
         match api::State::fetch(req.rocket()) {
             Some(state) => {
                 let app = &state.app;
-                let logger = &state.logger;
-                let session = app.new_session(logger.clone());
+                let logger = state.logger.new(o!("request_id" => api::RequestId::of(req).to_string()));
+                let session = app.new_session(logger);
                 return Outcome::Success(Context::new(state.clone(), session.detach()));
             }
             None => Outcome::Failure((Status::InternalServerError, "internal error".to_string())),
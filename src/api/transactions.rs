@@ -1,9 +1,9 @@
 use crate::{
-    api::{session::Context, Route},
+    api::{session::Context, DisputeResponse, Route},
     app::SanitizedError,
     model,
 };
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use rocket::fairing::AdHoc;
 use rocket::serde::{json::Json, Deserialize, Serialize};
 
@@ -13,7 +13,16 @@ pub struct TransactionRoute {}
 impl Route for TransactionRoute {
     fn stage() -> AdHoc {
         AdHoc::on_ignite("Transaction Routing", |rocket| async {
-            rocket.mount(TRANSACTION_ROUTE_BASE, rocket::routes![get_transaction, post_transaction])
+            rocket.mount(
+                TRANSACTION_ROUTE_BASE,
+                rocket::routes![
+                    get_transaction,
+                    get_transaction_dispute,
+                    get_transaction_disputes,
+                    get_transaction_fees,
+                    post_transaction
+                ],
+            )
         })
     }
 }
@@ -26,7 +35,21 @@ pub struct TransactionResponse {
     pub record_type: model::RecordType,
     pub client: u16,
     pub tx: u32,
-    pub amount: f32,
+    pub amount: model::Amount,
+    pub currency: model::Currency,
+    pub to_currency: Option<model::Currency>,
+    pub conversion: Option<ConversionResponse>,
+    // for fees, the record type of the tx with the same id they were charged for
+    pub charged_for: Option<model::RecordType>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(crate = "rocket::serde")]
+#[serde(rename_all = "camelCase")]
+pub struct ConversionResponse {
+    pub converted_amount: model::Amount,
+    pub rate: model::FxRate,
+    pub rate_time: Option<DateTime<Utc>>,
 }
 
 impl From<model::Transaction> for TransactionResponse {
@@ -36,6 +59,14 @@ impl From<model::Transaction> for TransactionResponse {
             client: tx.client,
             tx: tx.tx,
             amount: tx.amount,
+            currency: tx.currency,
+            to_currency: tx.to_currency,
+            conversion: tx.conversion.map(|c| ConversionResponse {
+                converted_amount: c.converted_amount,
+                rate: c.rate,
+                rate_time: c.rate_time,
+            }),
+            charged_for: tx.charged_for,
         };
     }
 }
@@ -50,6 +81,34 @@ pub async fn get_transaction(id: u32, context: Context) -> Result<Json<Transacti
     }
 }
 
+// Returns the latest dispute of a tx, whether it is still open or not.
+#[rocket::get("/<id>/dispute")]
+pub async fn get_transaction_dispute(id: u32, context: Context) -> Result<Json<DisputeResponse>, SanitizedError> {
+    let sess = context.session();
+    if let Some(dispute) = sess.get_dispute_by_reference_tx_id(id).await? {
+        return Ok(Json(dispute.into()));
+    } else {
+        return Err(SanitizedError::NotFound(format!("Transaction {id:} has not been disputed.")));
+    }
+}
+
+// Returns every dispute of a tx, oldest first. A tx can be disputed more than once depending on the
+// re-dispute policy.
+#[rocket::get("/<id>/disputes")]
+pub async fn get_transaction_disputes(id: u32, context: Context) -> Result<Json<Vec<DisputeResponse>>, SanitizedError> {
+    let sess = context.session();
+    let disputes = sess.get_disputes_by_reference_tx_id(id).await?;
+    return Ok(Json(disputes.into_iter().map(DisputeResponse::from).collect()));
+}
+
+// Returns the fees charged for a tx, including the fee of its chargeback if it was charged back.
+#[rocket::get("/<id>/fees")]
+pub async fn get_transaction_fees(id: u32, context: Context) -> Result<Json<Vec<TransactionResponse>>, SanitizedError> {
+    let sess = context.session();
+    let fees = sess.get_transaction_fees(id).await?;
+    return Ok(Json(fees.into_iter().map(TransactionResponse::from).collect()));
+}
+
 #[derive(Deserialize, Serialize)]
 #[serde(crate = "rocket::serde")]
 #[serde(rename_all = "camelCase")]
@@ -58,7 +117,14 @@ pub struct PostTransaction {
     pub record_type: model::RecordType,
     pub client: u16,
     pub tx: u32,
-    pub amount: f32,
+    pub amount: model::Amount,
+    // the default currency if unset, ignored for disputes, resolves and chargebacks which apply in
+    // the currency of the tx they reference
+    #[serde(default)]
+    pub currency: model::Currency,
+    // the currency conversions credit
+    #[serde(default)]
+    pub to_currency: Option<model::Currency>,
 }
 
 impl From<PostTransaction> for model::Transaction {
@@ -69,6 +135,10 @@ impl From<PostTransaction> for model::Transaction {
             client: tx.client,
             tx: tx.tx,
             amount: tx.amount,
+            currency: tx.currency,
+            to_currency: tx.to_currency,
+            conversion: None,
+            charged_for: None,
 
             creation_time: now,
             revision_number: 1,
@@ -84,6 +154,8 @@ impl From<model::Transaction> for PostTransaction {
             client: tx.client,
             tx: tx.tx,
             amount: tx.amount,
+            currency: tx.currency,
+            to_currency: tx.to_currency,
         };
     }
 }
@@ -99,6 +171,7 @@ pub async fn post_transaction(tx_json: Json<PostTransaction>, context: Context)
 mod test {
     use super::*;
     use crate::api::{transactions, API};
+    use crate::app;
     use rocket::http::{ContentType, Status};
     use rocket::serde::json;
 
@@ -118,7 +191,9 @@ mod test {
             tx: 1,
             client: 1,
             record_type: model::RecordType::Deposit,
-            amount: 10.0,
+            amount: model::Amount::from(10),
+            currency: model::Currency::USD,
+            to_currency: None,
         };
         let response = client
             .post(rocket::uri!("/transactions", transactions::post_transaction()))
@@ -135,10 +210,253 @@ mod test {
             tx: 1,
             client: 1,
             record_type: model::RecordType::Deposit,
-            amount: 10.0,
+            amount: model::Amount::from(10),
+            currency: model::Currency::USD,
+            to_currency: None,
+            conversion: None,
+            charged_for: None,
         };
         let resp_tx = response.into_json::<TransactionResponse>().await.unwrap();
 
         assert_eq!(expected, resp_tx);
     }
+
+    #[rocket::async_test]
+    async fn test_post_dispute_other_client() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        let deposit = PostTransaction {
+            tx: 1,
+            client: 1,
+            record_type: model::RecordType::Deposit,
+            amount: model::Amount::from(10),
+            currency: model::Currency::USD,
+            to_currency: None,
+        };
+        let response = client
+            .post(rocket::uri!("/transactions", transactions::post_transaction()))
+            .header(ContentType::JSON)
+            .body(json::to_string(&deposit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let dispute = PostTransaction {
+            tx: 1,
+            client: 2,
+            record_type: model::RecordType::Dispute,
+            amount: model::Amount::ZERO,
+            currency: model::Currency::USD,
+            to_currency: None,
+        };
+        let response = client
+            .post(rocket::uri!("/transactions", transactions::post_transaction()))
+            .header(ContentType::JSON)
+            .body(json::to_string(&dispute).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+        let error = response.into_json::<crate::api::ErrorResponse>().await.unwrap();
+        assert_eq!(error.code, "client_mismatch");
+    }
+
+    #[rocket::async_test]
+    async fn test_post_withdrawal_insufficient_funds() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        let withdrawal = PostTransaction {
+            tx: 1,
+            client: 1,
+            record_type: model::RecordType::Withdrawal,
+            amount: model::Amount::from(10),
+            currency: model::Currency::USD,
+            to_currency: None,
+        };
+        let response = client
+            .post(rocket::uri!("/transactions", transactions::post_transaction()))
+            .header(ContentType::JSON)
+            .body(json::to_string(&withdrawal).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        let error = response.into_json::<crate::api::ErrorResponse>().await.unwrap();
+        assert_eq!(error.code, "insufficient_funds");
+        assert_eq!(error.message, "not enough funds available");
+    }
+
+    #[rocket::async_test]
+    async fn test_get_transaction_fees() {
+        let api = API::new_test_api_with_app_config(|c| c.fees.deposit = Some(app::Fee::Flat(model::Amount::from(1)))).await;
+        let client = api.test_rocket_client().await;
+        let mut deposit = PostTransaction {
+            tx: 1,
+            client: 1,
+            record_type: model::RecordType::Deposit,
+            amount: model::Amount::from(10),
+            currency: model::Currency::USD,
+            to_currency: None,
+        };
+        let response = client
+            .post(rocket::uri!("/transactions", transactions::post_transaction()))
+            .header(ContentType::JSON)
+            .body(json::to_string(&deposit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let response = client.get(rocket::uri!("/transactions", get_transaction_fees(1))).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let expected = vec![TransactionResponse {
+            tx: 1,
+            client: 1,
+            record_type: model::RecordType::Fee,
+            amount: model::Amount::from(1),
+            currency: model::Currency::USD,
+            to_currency: None,
+            conversion: None,
+            charged_for: Some(model::RecordType::Deposit),
+        }];
+        assert_eq!(response.into_json::<Vec<TransactionResponse>>().await.unwrap(), expected);
+
+        let response = client.get("/clients/1").dispatch().await;
+        let resp_client = response.into_json::<crate::api::ClientResponse>().await.unwrap();
+        assert_eq!((resp_client.available, resp_client.fees), (model::Amount::from(9), model::Amount::from(1)));
+
+        // fees are only ever charged by the engine
+        deposit.tx = 2;
+        deposit.record_type = model::RecordType::Fee;
+        let response = client
+            .post(rocket::uri!("/transactions", transactions::post_transaction()))
+            .header(ContentType::JSON)
+            .body(json::to_string(&deposit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Forbidden);
+        let error = response.into_json::<crate::api::ErrorResponse>().await.unwrap();
+        assert_eq!(error.code, "engine_only");
+    }
+
+    #[rocket::async_test]
+    async fn test_post_duplicate_transaction() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        let mut deposit = PostTransaction {
+            tx: 1,
+            client: 1,
+            record_type: model::RecordType::Deposit,
+            amount: model::Amount::from(10),
+            currency: model::Currency::USD,
+            to_currency: None,
+        };
+        for _ in 0..2 {
+            let response = client
+                .post(rocket::uri!("/transactions", transactions::post_transaction()))
+                .header(ContentType::JSON)
+                .body(json::to_string(&deposit).unwrap())
+                .dispatch()
+                .await;
+            assert_eq!(response.status(), Status::Ok);
+        }
+
+        deposit.amount = model::Amount::from(5);
+        let response = client
+            .post(rocket::uri!("/transactions", transactions::post_transaction()))
+            .header(ContentType::JSON)
+            .body(json::to_string(&deposit).unwrap())
+            .dispatch()
+            .await;
+        assert_eq!(response.status(), Status::Conflict);
+        let error = response.into_json::<crate::api::ErrorResponse>().await.unwrap();
+        assert_eq!(error.code, "transaction_id_conflict");
+
+        let response = client.get("/clients/1").dispatch().await;
+        let resp_client = response.into_json::<crate::api::ClientResponse>().await.unwrap();
+        assert_eq!(resp_client.total, model::Amount::from(10));
+
+        let duplicates = api
+            .state
+            .app
+            .new_session(API::test_logger())
+            .get_duplicate_transactions_by_tx_id(1)
+            .await
+            .unwrap();
+        assert_eq!(duplicates.len(), 2);
+        assert_eq!(duplicates.iter().filter(|d| d.is_conflicting).count(), 1);
+    }
+
+    async fn post(client: &rocket::local::asynchronous::Client, record_type: model::RecordType, tx: u32, amount: i32, currency: model::Currency) -> Status {
+        let transaction = PostTransaction {
+            tx,
+            client: 1,
+            record_type,
+            amount: model::Amount::from(amount),
+            currency,
+            to_currency: None,
+        };
+        let response = client
+            .post(rocket::uri!("/transactions", transactions::post_transaction()))
+            .header(ContentType::JSON)
+            .body(json::to_string(&transaction).unwrap())
+            .dispatch()
+            .await;
+        return response.status();
+    }
+
+    #[rocket::async_test]
+    async fn test_post_transaction_locked_client() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        let sess = api.state.app.new_session(API::test_logger());
+        let eur = "EUR".parse().unwrap();
+
+        // a client locked before accounts were kept per currency, with neither an index of its
+        // accounts nor a lock of its own
+        let legacy = model::Client::new(1, model::Currency::USD, Some(model::Amount::from(10)));
+        let legacy = legacy
+            .with_patch(model::ClientPatch {
+                locked: Some(true),
+                ..Default::default()
+            })
+            .unwrap();
+        sess.store.add_client_revision(&legacy).await.unwrap();
+        assert_eq!(
+            post(&client, model::RecordType::Withdrawal, 1, 5, model::Currency::USD).await,
+            Status::Forbidden
+        );
+        assert_eq!(post(&client, model::RecordType::Deposit, 2, 5, eur).await, Status::Forbidden);
+        assert!(sess.get_client_by_id(1, eur).await.unwrap().is_none());
+    }
+
+    #[rocket::async_test]
+    async fn test_post_transaction_lock_contention() {
+        let api = API::new_test_api().await;
+        let client = api.test_rocket_client().await;
+        let sess = api.state.app.new_session(API::test_logger());
+        let (eur, gbp) = ("EUR".parse().unwrap(), "GBP".parse().unwrap());
+        assert_eq!(post(&client, model::RecordType::Deposit, 1, 10, eur).await, Status::Ok);
+        assert_eq!(post(&client, model::RecordType::Deposit, 2, 10, gbp).await, Status::Ok);
+
+        // a withdrawal that read the lock before a chargeback in another currency locked the client
+        let stale_lock = sess.get_client_lock(1).await.unwrap();
+        assert!(!stale_lock.locked);
+        assert_eq!(post(&client, model::RecordType::Dispute, 2, 0, gbp).await, Status::Ok);
+        assert_eq!(post(&client, model::RecordType::Chargeback, 2, 0, gbp).await, Status::Ok);
+        assert!(sess.get_client_lock(1).await.unwrap().locked);
+
+        let account = sess.get_client_by_id(1, eur).await.unwrap().unwrap();
+        let withdrawal = model::Transaction::from(PostTransaction {
+            tx: 3,
+            client: 1,
+            record_type: model::RecordType::Withdrawal,
+            amount: model::Amount::from(5),
+            currency: eur,
+            to_currency: None,
+        });
+        assert!(matches!(
+            sess.withdrawal(account, &stale_lock, withdrawal).await,
+            Err(app::SanitizedError::Contention)
+        ));
+        assert_eq!(post(&client, model::RecordType::Withdrawal, 3, 5, eur).await, Status::Forbidden);
+    }
 }
@@ -0,0 +1,106 @@
+use crate::{
+    app::{journal_entry, RejectionReason, SanitizedError, SanitizedResult, Session},
+    model,
+};
+
+impl Session<'_> {
+    // Applies an administrative adjustment to an existing client account. Unlike regular transactions,
+    // adjustments also apply to locked clients, which is how they get unlocked.
+    pub async fn process_adjustment(&self, adjustment: model::Adjustment) -> SanitizedResult<()> {
+        let transaction = adjustment.transaction;
+        if !transaction.record_type.is_adjustment() {
+            return Err(SanitizedError::Rejected(RejectionReason::InvalidAdjustmentType));
+        }
+        if adjustment.reason.trim().is_empty() {
+            return Err(SanitizedError::Rejected(RejectionReason::MissingReason));
+        }
+        // locks and unlocks don't move funds, credits and debits have to
+        let is_valid_amount = match transaction.record_type {
+            model::RecordType::Credit | model::RecordType::Debit => !transaction.amount.is_zero() && !transaction.amount.is_negative(),
+            _ => transaction.amount.is_zero(),
+        };
+        if !is_valid_amount {
+            return Err(SanitizedError::Rejected(RejectionReason::InvalidAmount));
+        }
+        info!(self.logger(), "{} adjustment: {}", transaction.record_type, adjustment.reason; "client_id" => transaction.client, "tx_id" => transaction.tx);
+
+        for _ in 0..3i32 {
+            // adjustments have their own tx ids, which don't conflict with regular transactions
+            if let Some(existing) = self.get_adjustment_by_tx_id(transaction.tx).await? {
+                return self.duplicate_transaction(existing.transaction, transaction).await;
+            }
+
+            let client = match self.get_client_by_id(transaction.client, transaction.currency).await? {
+                Some(c) => c,
+                None => {
+                    return Err(SanitizedError::NotFound(format!(
+                        "Client {} has no {} account.",
+                        transaction.client, transaction.currency
+                    )))
+                }
+            };
+
+            let patch = match transaction.record_type {
+                model::RecordType::Lock => model::ClientPatch {
+                    locked: Some(true),
+                    ..Default::default()
+                },
+                model::RecordType::Unlock => model::ClientPatch {
+                    locked: Some(false),
+                    ..Default::default()
+                },
+                model::RecordType::Credit => model::ClientPatch {
+                    available: Some(self.sanitize(client.available.checked_add(transaction.amount))?),
+                    ..Default::default()
+                },
+                _ => {
+                    let available = self.sanitize(client.available.checked_sub(transaction.amount))?;
+                    if available.is_negative() {
+                        return Err(SanitizedError::Rejected(RejectionReason::InsufficientFunds));
+                    }
+                    model::ClientPatch {
+                        available: Some(available),
+                        ..Default::default()
+                    }
+                }
+            };
+            let client_revision = self.sanitize(client.clone().with_patch(patch))?;
+            let lock = self.get_client_lock(client.id).await?;
+            let lock_revision = match transaction.record_type {
+                model::RecordType::Lock => lock.next(true),
+                model::RecordType::Unlock => self.unlocked_client_lock(&lock, &client_revision).await?,
+                _ => lock.next(lock.locked),
+            };
+            let event = self.sanitize(model::Event::new(&client, &client_revision, transaction.record_type, transaction.tx))?;
+
+            let entry = match transaction.record_type {
+                model::RecordType::Credit => journal_entry(&client_revision, &transaction).transfer(
+                    model::LedgerAccount::Settlement(client.currency),
+                    model::LedgerAccount::ClientAvailable(client.id, client.currency),
+                    transaction.amount,
+                ),
+                model::RecordType::Debit => journal_entry(&client_revision, &transaction).transfer(
+                    model::LedgerAccount::ClientAvailable(client.id, client.currency),
+                    model::LedgerAccount::Settlement(client.currency),
+                    transaction.amount,
+                ),
+                _ => journal_entry(&client_revision, &transaction),
+            };
+
+            match self.sanitize(
+                self.store
+                    .process_adjustment(&client_revision, &lock_revision, &adjustment, &event, &entry)
+                    .await,
+            ) {
+                Ok(_) => return Ok(()),
+                Err(SanitizedError::Contention) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        return Err(SanitizedError::Rejected(RejectionReason::Contention));
+    }
+
+    pub async fn get_adjustment_by_tx_id(&self, tx_id: u32) -> SanitizedResult<Option<model::Adjustment>> {
+        Ok(self.sanitize(self.store.get_adjustment_by_tx_id(tx_id).await)?)
+    }
+}
@@ -1,23 +1,163 @@
 use crate::{
-    app::{distant_future, distant_past, SanitizedResult, Session},
-    model::client::*,
+    app::{distant_future, distant_past, Page, PageQuery, SanitizedResult, Session},
+    model::{client::*, Amount, Currency},
 };
 use chrono::{DateTime, Utc};
 
+// Filters for listing clients, unset fields match every client.
+#[derive(Clone, Debug, Default)]
+pub struct ClientFilter {
+    pub locked: Option<bool>,
+    // true to only match clients with funds held, false for clients without
+    pub held: Option<bool>,
+    pub min_total: Option<Amount>,
+    pub max_total: Option<Amount>,
+    pub currency: Option<Currency>,
+}
+
+impl ClientFilter {
+    pub fn matches(&self, client: &Client) -> bool {
+        return self.locked.map_or(true, |locked| client.locked == locked)
+            && self.held.map_or(true, |held| !client.held.is_zero() == held)
+            && self.min_total.map_or(true, |min| client.total >= min)
+            && self.max_total.map_or(true, |max| client.total <= max)
+            && self.currency.map_or(true, |currency| client.currency == currency);
+    }
+}
+
 impl Session<'_> {
     pub async fn get_clients_by_time_range(&self, min_time: DateTime<Utc>, max_time: DateTime<Utc>, limit: i32) -> SanitizedResult<Vec<Client>> {
         Ok(self.sanitize(self.store.get_clients_by_time_range(min_time, max_time, limit).await)?)
     }
 
-    pub async fn get_client_by_id(&self, id: u16) -> SanitizedResult<Option<Client>> {
-        Ok(self.sanitize(self.store.get_client_by_id(id).await)?)
+    // Returns a client's account in a currency.
+    pub async fn get_client_by_id(&self, id: u16, currency: Currency) -> SanitizedResult<Option<Client>> {
+        Ok(self.sanitize(self.store.get_client_by_id(id, currency).await)?)
+    }
+
+    // Returns every account of a client, one per currency it has transacted in. Accounts stored
+    // before clients had an account per currency, which are always in the default currency, aren't
+    // in the store's index of a client's accounts.
+    pub async fn get_client_accounts(&self, id: u16) -> SanitizedResult<Vec<Client>> {
+        let mut accounts = self.sanitize(self.store.get_client_accounts(id).await)?;
+        if !accounts.iter().any(|c| c.currency == Currency::default()) {
+            if let Some(legacy) = self.get_client_by_id(id, Currency::default()).await? {
+                accounts.insert(0, legacy);
+            }
+        }
+        return Ok(accounts);
+    }
+
+    // Returns a client's lock, which applies to the client as a whole, so a chargeback in one
+    // currency also freezes the client's accounts in every other one. Clients that haven't been
+    // written to since the lock was kept per client are locked if any of their accounts is.
+    pub async fn get_client_lock(&self, id: u16) -> SanitizedResult<ClientLock> {
+        if let Some(lock) = self.sanitize(self.store.get_client_lock(id).await)? {
+            return Ok(lock);
+        }
+        let locked = self.get_client_accounts(id).await?.iter().any(|c| c.locked);
+        return Ok(ClientLock::new(id, locked));
+    }
+
+    // Returns the next revision of a client's lock once one of its accounts is unlocked, the client
+    // stays locked while any of its other accounts is.
+    pub(crate) async fn unlocked_client_lock(&self, lock: &ClientLock, unlocked: &Client) -> SanitizedResult<ClientLock> {
+        let accounts = self.get_client_accounts(lock.client).await?;
+        let locked = accounts.iter().any(|c| c.currency != unlocked.currency && c.locked);
+        return Ok(lock.next(locked || unlocked.locked));
     }
 
     pub async fn get_all_clients(&self) -> SanitizedResult<Vec<Client>> {
         Ok(self.get_clients_by_time_range(distant_past(), distant_future(), 0).await?)
     }
 
+    // Pages through clients by the time they were last updated. A client that is updated while
+    // paging moves to the end of the set, so in ascending order it may be listed twice but is never
+    // skipped.
+    pub async fn get_clients(&self, page: &PageQuery, filter: &ClientFilter) -> SanitizedResult<Page<Client>> {
+        let fetch = |min, max, limit| self.get_clients_by_time_range(min, max, limit);
+        return page.scan(fetch, |c| c.revision_time, |c| c.account_id(), |c| filter.matches(c)).await;
+    }
+
+    pub async fn get_client_revision(&self, id: u16, currency: Currency, revision_number: u32) -> SanitizedResult<Option<Client>> {
+        Ok(self.sanitize(self.store.get_client_revision(id, currency, revision_number).await)?)
+    }
+
+    // Lists up to limit revisions of a client's account, oldest first, starting after the given
+    // revision number. Returns None if the account doesn't exist.
+    pub async fn get_client_revisions(&self, id: u16, currency: Currency, after: u32, limit: usize) -> SanitizedResult<Option<Vec<Client>>> {
+        let latest = match self.get_client_by_id(id, currency).await? {
+            Some(c) => c.revision_number,
+            None => return Ok(None),
+        };
+        if limit == 0 || after >= latest {
+            return Ok(Some(vec![]));
+        }
+
+        let max = latest.min(after.saturating_add(limit as u32));
+        Ok(Some(self.sanitize(self.store.get_client_revisions(id, currency, after + 1, max).await)?))
+    }
+
+    // Returns a client's account as it was at the given time, i.e. its latest revision made at or
+    // before it. Returns None if the account didn't exist yet.
+    pub async fn get_client_as_of(&self, id: u16, currency: Currency, time: DateTime<Utc>) -> SanitizedResult<Option<Client>> {
+        let latest = self.get_client_by_id(id, currency).await?;
+        return Ok(self.clients_as_of(latest.into_iter().collect(), time).await?.pop());
+    }
+
+    // Returns every client account that existed at the given time, as it was then.
+    pub async fn get_all_clients_as_of(&self, time: DateTime<Utc>) -> SanitizedResult<Vec<Client>> {
+        return self.clients_as_of(self.get_all_clients().await?, time).await;
+    }
+
+    // Returns the given accounts as they were at the given time, skipping those that didn't exist
+    // yet. Revisions are numbered from 1 and their times only increase, so this binary searches for
+    // the last one made at or before the time. The accounts are searched together, with a single
+    // batch of revision reads per step.
+    async fn clients_as_of(&self, latest: Vec<Client>, time: DateTime<Utc>) -> SanitizedResult<Vec<Client>> {
+        let mut searches: Vec<RevisionSearch> = latest
+            .into_iter()
+            .map(|c| RevisionSearch {
+                id: c.id,
+                currency: c.currency,
+                lo: if c.revision_time <= time { c.revision_number } else { 1 },
+                hi: c.revision_number,
+                found: if c.revision_time <= time { Some(c) } else { None },
+            })
+            .collect();
+
+        loop {
+            let mut pending: Vec<&mut RevisionSearch> = searches.iter_mut().filter(|s| s.lo < s.hi).collect();
+            if pending.is_empty() {
+                break;
+            }
+            let revisions: Vec<_> = pending.iter().map(|s| (s.id, s.currency, s.lo + (s.hi - s.lo) / 2)).collect();
+            let found = self.sanitize(self.store.get_client_revisions_by_number(&revisions).await)?;
+            for ((search, (_, _, mid)), revision) in pending.iter_mut().zip(revisions).zip(found) {
+                match revision {
+                    Some(c) if c.revision_time <= time => {
+                        search.lo = mid + 1;
+                        search.found = Some(c);
+                    }
+                    _ => search.hi = mid,
+                }
+            }
+        }
+        return Ok(searches.into_iter().filter_map(|s| s.found).collect());
+    }
+
     pub async fn add_client(&self, client: &Client) -> SanitizedResult<()> {
         Ok(self.sanitize(self.store.add_client(client).await)?)
     }
 }
+
+// The state of the binary search for an account's revision at a given time.
+struct RevisionSearch {
+    id: u16,
+    currency: Currency,
+    // the range of revision numbers left to search
+    lo: u32,
+    hi: u32,
+    // the latest revision found at or before the time so far
+    found: Option<Client>,
+}
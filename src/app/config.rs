@@ -1,21 +1,155 @@
-use crate::store;
 use crate::Result;
+use crate::{app, model, store};
 use serde::Deserialize;
 
-#[derive(Debug, Default, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 #[serde(rename_all = "PascalCase", default)]
 pub struct Config {
     pub store: store::Config,
+
+    // Decides what happens when a dispute references a withdrawal instead of a deposit.
+    pub withdrawal_disputes: WithdrawalDisputePolicy,
+
+    // Decides what happens when disputing a deposit would make the available funds negative.
+    pub negative_balances: NegativeBalancePolicy,
+
+    // Decides whether a tx can be disputed again once its previous dispute has been resolved.
+    pub redisputes: RedisputePolicy,
+
+    // Decides which transactions still go through once a client is locked.
+    pub locked_clients: LockPolicy,
+
+    // The path of a CSV or YAML file with the exchange rates conversions are made at. Conversions
+    // are rejected if it isn't set.
+    #[serde(rename = "FXRates")]
+    pub fx_rates: Option<String>,
+
+    // The fees charged for each record type, nothing is charged by default.
+    pub fees: app::FeeSchedule,
+
+    // The bearer token admin API requests must be authenticated with. Admin operations are
+    // disabled if it isn't set.
+    pub admin_token: Option<String>,
+
+    // If greater than 1, CSV records are sharded by client across this many worker tasks. Records
+    // of a given client are always processed in order by the same worker. A tx id belongs to the
+    // first client using it in the file, even if that record ends up rejected, and other clients
+    // reusing it are rejected as conflicts.
+    #[serde(rename = "CSVWorkers")]
+    pub csv_workers: usize,
 }
 
 impl Config {
     pub fn validate(&self) -> Result<()> {
         self.store.validate()?;
+        self.fees.validate()?;
         Ok(())
     }
 
     pub async fn load_from_env(&mut self, prefix: &str) -> Result<()> {
         self.store.load_from_env([prefix, "STORE_"].join("").as_str())?;
+        if let Ok(withdrawal_disputes) = std::env::var([prefix, "WITHDRAWALDISPUTES"].join("").as_str()) {
+            self.withdrawal_disputes = serde_yaml::from_str(&withdrawal_disputes)?;
+        }
+        if let Ok(negative_balances) = std::env::var([prefix, "NEGATIVEBALANCES"].join("").as_str()) {
+            self.negative_balances = serde_yaml::from_str(&negative_balances)?;
+        }
+        if let Ok(redisputes) = std::env::var([prefix, "REDISPUTES"].join("").as_str()) {
+            self.redisputes = serde_yaml::from_str(&redisputes)?;
+        }
+        if let Ok(locked_clients) = std::env::var([prefix, "LOCKEDCLIENTS"].join("").as_str()) {
+            self.locked_clients = serde_yaml::from_str(&locked_clients)?;
+        }
+        if let Ok(fx_rates) = std::env::var([prefix, "FXRATES"].join("").as_str()) {
+            self.fx_rates = Some(fx_rates);
+        }
+        if let Ok(fees) = std::env::var([prefix, "FEES"].join("").as_str()) {
+            self.fees = serde_yaml::from_str(&fees)?;
+        }
+        if let Ok(admin_token) = std::env::var([prefix, "ADMINTOKEN"].join("").as_str()) {
+            self.admin_token = Some(admin_token);
+        }
+        if let Ok(csv_workers) = std::env::var([prefix, "CSVWORKERS"].join("").as_str()) {
+            self.csv_workers = csv_workers.parse()?;
+        }
         Ok(())
     }
 }
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+pub enum WithdrawalDisputePolicy {
+    // Disputes referencing a withdrawal are skipped as user errors.
+    #[default]
+    Reject,
+
+    // The withdrawn funds are held while the dispute is open: held and total increase, available
+    // stays the same. A resolve releases the hold and a chargeback credits the funds back to
+    // available before locking the client.
+    Hold,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+pub enum NegativeBalancePolicy {
+    // The whole deposit is held and available goes negative.
+    #[default]
+    Allow,
+
+    // Only what is still available is held, the part of the deposit that has already been spent
+    // can't be taken back. A chargeback then only removes the held funds.
+    CapHold,
+
+    // The dispute is rejected as insufficient funds.
+    Reject,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+pub enum RedisputePolicy {
+    // A tx can only ever be disputed once.
+    #[default]
+    Never,
+
+    // A tx can be disputed again every time its previous dispute is resolved.
+    AfterResolve,
+
+    // Like AfterResolve, but a tx can only be disputed again this many times.
+    Limit(u32),
+}
+
+impl RedisputePolicy {
+    // Returns true if a tx whose latest dispute was resolved can be disputed again. Charged back
+    // disputes are final whatever the policy.
+    pub fn allows(&self, previous_disputes: u32) -> bool {
+        match self {
+            RedisputePolicy::Never => false,
+            RedisputePolicy::AfterResolve => true,
+            RedisputePolicy::Limit(n) => previous_disputes <= *n,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+pub enum LockPolicy {
+    // Every transaction of a locked client is rejected.
+    #[default]
+    RejectAll,
+
+    // Resolves and chargebacks still go through so that disputes opened before the lock can be
+    // settled and their held funds released. Everything else is rejected.
+    AllowDisputeSettlement,
+
+    // Disputes, resolves and chargebacks all go through, only deposits and withdrawals are rejected.
+    AllowDisputes,
+}
+
+impl LockPolicy {
+    // Returns true if a transaction of the given type can be applied to a locked client.
+    // Adjustments aren't subject to this, they always apply.
+    pub fn allows(&self, record_type: model::RecordType) -> bool {
+        match (self, record_type) {
+            (LockPolicy::RejectAll, _) => false,
+            (LockPolicy::AllowDisputeSettlement, model::RecordType::Resolve | model::RecordType::Chargeback) => true,
+            (LockPolicy::AllowDisputes, model::RecordType::Dispute | model::RecordType::Resolve | model::RecordType::Chargeback) => true,
+            _ => false,
+        }
+    }
+}
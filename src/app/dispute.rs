@@ -1,10 +1,31 @@
 use crate::{
     app::{SanitizedResult, Session},
-    model::Dispute,
+    model::{Dispute, Id},
 };
 
 impl Session<'_> {
+    pub async fn get_dispute_by_id(&self, id: &Id) -> SanitizedResult<Option<Dispute>> {
+        Ok(self.sanitize(self.store.get_dispute_by_id(id).await)?)
+    }
+
+    // Returns every revision of a dispute, from when it was opened to its current state.
+    pub async fn get_dispute_revisions(&self, id: &Id) -> SanitizedResult<Option<Vec<Dispute>>> {
+        let latest = match self.get_dispute_by_id(id).await? {
+            Some(d) => d.revision_number,
+            None => return Ok(None),
+        };
+        Ok(Some(self.sanitize(self.store.get_dispute_revisions(id, latest).await)?))
+    }
+
     pub async fn get_dispute_by_reference_tx_id(&self, id: u32) -> SanitizedResult<Option<Dispute>> {
         Ok(self.sanitize(self.store.get_dispute_by_reference_tx_id(id).await)?)
     }
+
+    pub async fn get_disputes_by_reference_tx_id(&self, id: u32) -> SanitizedResult<Vec<Dispute>> {
+        Ok(self.sanitize(self.store.get_disputes_by_reference_tx_id(id).await)?)
+    }
+
+    pub async fn get_client_disputes(&self, client_id: u16, include_closed: bool) -> SanitizedResult<Vec<Dispute>> {
+        Ok(self.sanitize(self.store.get_client_disputes(client_id, include_closed).await)?)
+    }
 }
@@ -1,7 +1,8 @@
 use rusoto_core;
 use rusoto_credential;
 
-use crate::{store, Error};
+use crate::{model, store, Error};
+use serde::ser::{Serialize, Serializer};
 use std::fmt;
 
 #[derive(Clone, Debug)]
@@ -11,9 +12,121 @@ pub enum SanitizedError {
     Contention,
     InternalError,
     IncorrectRevisionNumber,
+    // Returned when a tx id is reused for a transaction that differs from the one already processed.
+    TransactionIdConflict(u32),
+    // Returned when a transaction breaks one of the engine's business rules.
+    Rejected(RejectionReason),
     UserError(String),
 }
 
+// The reasons a transaction can be rejected for. These are stable, machine-readable codes that end
+// up in rejection reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RejectionReason {
+    ClientLocked,
+    InsufficientFunds,
+    AmountOverflow,
+    InvalidAmount,
+    ReferencedTxNotFound,
+    ReferencedTxNotDisputable,
+    WithdrawalDisputeNotAllowed,
+    ClientMismatch,
+    AlreadyDisputed,
+    RedisputeNotAllowed,
+    NotDisputed,
+    DisputeClosed,
+    TransactionIdConflict,
+    AdminOnly,
+    InvalidConversion,
+    FxRateNotFound,
+    FeeExceedsAmount,
+    EngineOnly,
+    InvalidAdjustmentType,
+    MissingReason,
+    Contention,
+    MalformedRecord,
+}
+
+impl RejectionReason {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ClientLocked => "client_locked",
+            Self::InsufficientFunds => "insufficient_funds",
+            Self::AmountOverflow => "amount_overflow",
+            Self::InvalidAmount => "invalid_amount",
+            Self::ReferencedTxNotFound => "referenced_tx_not_found",
+            Self::ReferencedTxNotDisputable => "referenced_tx_not_disputable",
+            Self::WithdrawalDisputeNotAllowed => "withdrawal_dispute_not_allowed",
+            Self::ClientMismatch => "client_mismatch",
+            Self::AlreadyDisputed => "already_disputed",
+            Self::RedisputeNotAllowed => "redispute_not_allowed",
+            Self::NotDisputed => "not_disputed",
+            Self::DisputeClosed => "dispute_closed",
+            Self::TransactionIdConflict => "transaction_id_conflict",
+            Self::AdminOnly => "admin_only",
+            Self::InvalidConversion => "invalid_conversion",
+            Self::FxRateNotFound => "fx_rate_not_found",
+            Self::FeeExceedsAmount => "fee_exceeds_amount",
+            Self::EngineOnly => "engine_only",
+            Self::InvalidAdjustmentType => "invalid_adjustment_type",
+            Self::MissingReason => "missing_reason",
+            Self::Contention => "contention",
+            Self::MalformedRecord => "malformed_record",
+        }
+    }
+}
+
+impl fmt::Display for RejectionReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ClientLocked => write!(f, "client is locked"),
+            Self::InsufficientFunds => write!(f, "not enough funds available"),
+            Self::AmountOverflow => write!(f, "amount overflow"),
+            Self::InvalidAmount => write!(f, "invalid amount"),
+            Self::ReferencedTxNotFound => write!(f, "referenced tx does not exist"),
+            Self::ReferencedTxNotDisputable => write!(f, "referenced tx cannot be disputed"),
+            Self::WithdrawalDisputeNotAllowed => write!(f, "referenced tx is a withdrawal, which cannot be disputed"),
+            Self::ClientMismatch => write!(f, "referenced tx belongs to another client"),
+            Self::AlreadyDisputed => write!(f, "tx is already under dispute"),
+            Self::RedisputeNotAllowed => write!(f, "tx has already been disputed and cannot be disputed again"),
+            Self::NotDisputed => write!(f, "tx is not disputed"),
+            Self::DisputeClosed => write!(f, "tx is no longer disputed"),
+            Self::TransactionIdConflict => write!(f, "tx id has already been processed with different details"),
+            Self::AdminOnly => write!(f, "record type can only be applied by an administrator"),
+            Self::InvalidConversion => write!(f, "conversions need a to_currency different from their currency"),
+            Self::FxRateNotFound => write!(f, "no fx rate for the currency pair"),
+            Self::FeeExceedsAmount => write!(f, "amount does not cover the fee"),
+            Self::EngineOnly => write!(f, "record type can only be created by the engine"),
+            Self::InvalidAdjustmentType => write!(f, "record type is not an adjustment"),
+            Self::MissingReason => write!(f, "adjustments require a reason"),
+            Self::Contention => write!(f, "transaction contention"),
+            Self::MalformedRecord => write!(f, "record could not be parsed"),
+        }
+    }
+}
+
+impl Serialize for RejectionReason {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl SanitizedError {
+    // A stable, machine-readable code for the error. Rejections use their reason's code.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Unauthorized => "unauthorized",
+            Self::NotFound(_) => "not_found",
+            Self::Contention => "contention",
+            Self::InternalError => "internal_error",
+            Self::IncorrectRevisionNumber => "incorrect_revision_number",
+            Self::TransactionIdConflict(_) => RejectionReason::TransactionIdConflict.code(),
+            Self::Rejected(reason) => reason.code(),
+            Self::UserError(_) => "user_error",
+        }
+    }
+}
+
 pub fn user_error<S: Into<String>>(message: S) -> SanitizedError {
     SanitizedError::UserError(message.into())
 }
@@ -28,6 +141,8 @@ impl fmt::Display for SanitizedError {
             Self::Contention => write!(f, "Operation failed due to contention. Please try again."),
             Self::InternalError => write!(f, "An internal error has occurred."),
             Self::IncorrectRevisionNumber => write!(f, "This object has been modified. Please try again using the latest revision."),
+            Self::TransactionIdConflict(tx) => write!(f, "Transaction {} has already been processed with different details.", tx),
+            Self::Rejected(reason) => write!(f, "{}", reason),
             Self::UserError(message) => write!(f, "{}", message),
         }
     }
@@ -82,6 +197,22 @@ impl Sanitizable for store::Error {
     }
 }
 
+impl Sanitizable for model::AmountError {
+    fn sanitize(self) -> (SanitizedError, Option<Error>) {
+        match self {
+            model::AmountError::Overflow => (SanitizedError::Rejected(RejectionReason::AmountOverflow), None),
+            _ => (SanitizedError::Rejected(RejectionReason::InvalidAmount), None),
+        }
+    }
+}
+
+// Disputes can only be closed once, by a resolve or a chargeback.
+impl Sanitizable for model::DisputeTransitionError {
+    fn sanitize(self) -> (SanitizedError, Option<Error>) {
+        (SanitizedError::Rejected(RejectionReason::DisputeClosed), None)
+    }
+}
+
 pub fn sanitize<T, E: Sanitizable>(logger: &slog::Logger, r: std::result::Result<T, E>) -> SanitizedResult<T> {
     sanitize_with_error_func(|msg| error!(logger, "{}", msg), r)
 }
@@ -0,0 +1,50 @@
+use crate::{
+    app::{SanitizedError, SanitizedResult, Session},
+    model::{Client, Currency, Event},
+};
+use chrono::Utc;
+
+impl Session<'_> {
+    pub async fn get_client_events(&self, id: u16, currency: Currency) -> SanitizedResult<Vec<Event>> {
+        Ok(self.sanitize(self.store.get_client_events(id, currency).await)?)
+    }
+
+    // Rebuilds a client's account in a currency by folding its events, without looking at the
+    // stored account. Returns None if the account has no events.
+    pub async fn replay_client(&self, id: u16, currency: Currency) -> SanitizedResult<Option<Client>> {
+        let mut client = None;
+        for event in self.get_client_events(id, currency).await? {
+            client = Some(self.sanitize(event.apply(client))?);
+        }
+        return Ok(client);
+    }
+
+    // Replaces the stored account of a client with the one replayed from its events. The rebuilt
+    // account is stored as a new revision following the latest stored one, which may not have an
+    // event if it is the one being repaired, so that the next transaction can follow it.
+    pub async fn rebuild_client(&self, id: u16, currency: Currency) -> SanitizedResult<Option<Client>> {
+        for _ in 0..3i32 {
+            let mut client = match self.replay_client(id, currency).await? {
+                Some(c) => c,
+                None => return Ok(None),
+            };
+            let stored = self.get_client_by_id(id, currency).await?;
+            client.revision_number = stored.map_or(client.revision_number, |c| c.revision_number.max(client.revision_number)) + 1;
+            client.revision_time = Utc::now();
+
+            // the rebuilt account may lock or unlock the client
+            let lock = self.get_client_lock(id).await?;
+            let lock_revision = match client.locked {
+                true => lock.next(true),
+                false => self.unlocked_client_lock(&lock, &client).await?,
+            };
+
+            match self.sanitize(self.store.restore_client(&client, &lock_revision, &Event::rebuilt(&client)).await) {
+                Ok(_) => return Ok(Some(client)),
+                Err(SanitizedError::Contention) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+        return Err(SanitizedError::Contention);
+    }
+}
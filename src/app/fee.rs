@@ -0,0 +1,151 @@
+use crate::model::{Amount, AmountError, RecordType};
+use serde::Deserialize;
+
+// The fees charged per record type. Record types without a fee, and disputes and resolves which
+// can't have one, are free.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "PascalCase", default)]
+pub struct FeeSchedule {
+    pub deposit: Option<Fee>,
+    pub withdrawal: Option<Fee>,
+    pub conversion: Option<Fee>,
+    // charged on top of the charged back funds, based on the amount of the disputed tx
+    pub chargeback: Option<Fee>,
+}
+
+impl FeeSchedule {
+    // Returns the fee of a tx of the given type and amount, zero if it is free.
+    pub fn fee(&self, record_type: RecordType, amount: Amount) -> Result<Amount, AmountError> {
+        let fee = match record_type {
+            RecordType::Deposit => &self.deposit,
+            RecordType::Withdrawal => &self.withdrawal,
+            RecordType::Conversion => &self.conversion,
+            RecordType::Chargeback => &self.chargeback,
+            _ => &None,
+        };
+        return match fee {
+            Some(fee) => fee.charge(amount),
+            None => Ok(Amount::ZERO),
+        };
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        for fee in [&self.deposit, &self.withdrawal, &self.conversion, &self.chargeback].into_iter().flatten() {
+            fee.validate()?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub enum Fee {
+    // The same amount whatever the tx amount.
+    Flat(Amount),
+    // A percentage of the tx amount, e.g. 1.5 for 1.5%, rounded down to the amount precision.
+    Percentage(Amount),
+    // The fee of the first tier the tx amount is within. The last tier is unbounded, so every
+    // amount is within one.
+    Tiered(Vec<FeeTier>),
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct FeeTier {
+    // the largest amount in the tier, unbounded if unset
+    #[serde(default)]
+    pub up_to: Option<Amount>,
+    pub fee: Fee,
+}
+
+impl Fee {
+    pub fn charge(&self, amount: Amount) -> Result<Amount, AmountError> {
+        match self {
+            Fee::Flat(fee) => Ok(*fee),
+            Fee::Percentage(percentage) => {
+                // a percentage of 100 charges the whole amount
+                let fee = amount.scaled() as i128 * percentage.scaled() as i128 / Amount::from(100).scaled() as i128;
+                return i64::try_from(fee).map(Amount::from_scaled).map_err(|_| AmountError::Overflow);
+            }
+            Fee::Tiered(tiers) => match tiers.iter().find(|t| t.up_to.map_or(true, |up_to| amount <= up_to)) {
+                Some(tier) => tier.fee.charge(amount),
+                None => Ok(Amount::ZERO),
+            },
+        }
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Fee::Flat(amount) | Fee::Percentage(amount) if amount.is_negative() => Err(format!("fee {} is negative", amount)),
+            Fee::Percentage(percentage) if *percentage > Amount::from(100) => Err(format!("fee percentage {} is above 100", percentage)),
+            Fee::Flat(_) | Fee::Percentage(_) => Ok(()),
+            Fee::Tiered(tiers) => {
+                if tiers.last().map_or(true, |tier| tier.up_to.is_some()) {
+                    return Err("the last fee tier has to be without UpTo".to_string());
+                }
+                // tiers are matched in order, so a tier that doesn't go above the previous one is never used
+                for (tier, next) in tiers.iter().zip(tiers.iter().skip(1).map(Some).chain([None])) {
+                    tier.fee.validate()?;
+                    match (tier.up_to, next.map(|n| n.up_to)) {
+                        (None, Some(_)) => return Err("only the last fee tier can be without UpTo".to_string()),
+                        (Some(up_to), Some(Some(next))) if next <= up_to => {
+                            return Err(format!("fee tier up to {} doesn't follow the tier up to {}", next, up_to));
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_fee() {
+        let schedule: FeeSchedule = serde_yaml::from_str(
+            "
+Deposit: !Flat 0.5
+Withdrawal: !Percentage 1.5
+Chargeback: !Tiered
+  - UpTo: 100
+    Fee: !Flat 10
+  - Fee: !Percentage 10
+",
+        )
+        .unwrap();
+        assert_eq!(schedule.validate(), Ok(()));
+
+        let fee = |record_type, amount: &str| schedule.fee(record_type, amount.parse().unwrap()).unwrap().to_string();
+        assert_eq!(fee(RecordType::Deposit, "20"), "0.5000");
+        // 1.5% of 12.3456 is 0.185184, rounded down
+        assert_eq!(fee(RecordType::Withdrawal, "12.3456"), "0.1851");
+        assert_eq!(fee(RecordType::Chargeback, "100"), "10.0000");
+        assert_eq!(fee(RecordType::Chargeback, "250"), "25.0000");
+        assert_eq!(fee(RecordType::Conversion, "20"), "0.0000");
+        assert_eq!(fee(RecordType::Dispute, "20"), "0.0000");
+
+        let negative = FeeSchedule {
+            deposit: Some(Fee::Flat("-1".parse().unwrap())),
+            ..Default::default()
+        };
+        assert!(negative.validate().is_err());
+
+        let is_invalid = |fee: &str| {
+            let schedule: FeeSchedule = serde_yaml::from_str(&format!("Deposit: {}", fee)).unwrap();
+            schedule.validate().is_err()
+        };
+        assert!(!is_invalid("!Percentage 100"));
+        assert!(is_invalid("!Percentage 100.5"));
+        assert!(!is_invalid("!Tiered [{UpTo: 100, Fee: !Flat 1}, {Fee: !Flat 2}]"));
+        assert!(is_invalid("!Tiered [{UpTo: 100, Fee: !Flat 1}, {UpTo: 100, Fee: !Flat 2}, {Fee: !Flat 3}]"));
+        assert!(is_invalid("!Tiered [{UpTo: 100, Fee: !Flat 1}, {UpTo: 50, Fee: !Flat 2}, {Fee: !Flat 3}]"));
+        assert!(is_invalid("!Tiered [{Fee: !Flat 1}, {UpTo: 50, Fee: !Flat 2}]"));
+        assert!(is_invalid("!Tiered [{Fee: !Percentage 101}]"));
+        // amounts above the last tier would otherwise go free
+        assert!(is_invalid("!Tiered [{UpTo: 100, Fee: !Flat 1}]"));
+        assert!(is_invalid("!Tiered []"));
+    }
+}
@@ -0,0 +1,104 @@
+use crate::{
+    model::{Currency, FxRate},
+    Result,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use std::{collections::HashMap, fs::File};
+
+// A row of a rate table file. Rates without an as_of time apply at any time, the others from that
+// time until a later rate of the same pair takes over.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct RateTableEntry {
+    pub from: Currency,
+    pub to: Currency,
+    pub rate: FxRate,
+    #[serde(default)]
+    pub as_of: Option<DateTime<Utc>>,
+}
+
+// The rate a conversion is made at, along with when it took effect.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FxQuote {
+    pub rate: FxRate,
+    pub rate_time: Option<DateTime<Utc>>,
+}
+
+// Exchange rates by currency pair. Only the pairs in the table can be converted, rates aren't
+// inverted or chained through other currencies.
+#[derive(Clone, Debug, Default)]
+pub struct RateTable {
+    rates: HashMap<(Currency, Currency), Vec<FxQuote>>,
+}
+
+impl RateTable {
+    pub fn new(entries: Vec<RateTableEntry>) -> Self {
+        let mut rates: HashMap<(Currency, Currency), Vec<FxQuote>> = HashMap::new();
+        for entry in entries {
+            rates.entry((entry.from, entry.to)).or_default().push(FxQuote {
+                rate: entry.rate,
+                rate_time: entry.as_of,
+            });
+        }
+        // None sorts first, so rates without a time are only used until a timed one applies
+        for quotes in rates.values_mut() {
+            quotes.sort_by_key(|q| q.rate_time);
+        }
+        return Self { rates };
+    }
+
+    // Loads a rate table from a YAML file if its extension is .yaml or .yml, from a CSV file
+    // otherwise. Both have from, to, rate and optionally as_of fields.
+    pub fn load(path: &str) -> Result<Self> {
+        let file = File::open(path)?;
+        let entries: Vec<RateTableEntry> = if path.ends_with(".yaml") || path.ends_with(".yml") {
+            serde_yaml::from_reader(file)?
+        } else {
+            let mut rdr = csv::ReaderBuilder::new().has_headers(true).trim(csv::Trim::All).from_reader(file);
+            rdr.deserialize().collect::<std::result::Result<_, _>>()?
+        };
+        return Ok(Self::new(entries));
+    }
+
+    // Returns the rate of a pair at the given time, i.e. the latest one that took effect at or
+    // before it.
+    pub fn quote(&self, from: Currency, to: Currency, time: DateTime<Utc>) -> Option<FxQuote> {
+        let quotes = self.rates.get(&(from, to))?;
+        return quotes.iter().rev().find(|q| q.rate_time.map_or(true, |t| t <= time)).copied();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_quote() {
+        let (usd, eur) = (Currency::USD, "EUR".parse().unwrap());
+        let entry = |rate: &str, as_of: Option<&str>| RateTableEntry {
+            from: usd,
+            to: eur,
+            rate: rate.parse().unwrap(),
+            as_of: as_of.map(|t| t.parse().unwrap()),
+        };
+        let table = RateTable::new(vec![
+            entry("0.95", Some("2022-10-02T00:00:00Z")),
+            entry("0.9", None),
+            entry("0.92", Some("2022-10-01T00:00:00Z")),
+        ]);
+
+        let rate_at = |time: &str| table.quote(usd, eur, time.parse().unwrap()).map(|q| q.rate.to_string());
+        assert_eq!(rate_at("2022-09-30T00:00:00Z").as_deref(), Some("0.90000000"));
+        assert_eq!(rate_at("2022-10-01T12:00:00Z").as_deref(), Some("0.92000000"));
+        assert_eq!(rate_at("2022-10-03T00:00:00Z").as_deref(), Some("0.95000000"));
+        assert_eq!(table.quote(eur, usd, Utc::now()), None);
+    }
+
+    #[test]
+    fn test_load() {
+        let csv = RateTable::load("tests/test_data/fx_rates.csv").unwrap();
+        let yaml = RateTable::load("tests/test_data/fx_rates.yaml").unwrap();
+        assert_eq!(csv.rates, yaml.rates);
+        assert_eq!(csv.rates.len(), 3);
+    }
+}
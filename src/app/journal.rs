@@ -0,0 +1,160 @@
+use crate::{
+    app::{distant_future, distant_past, SanitizedResult, Session},
+    model::{self, Amount, AmountError, Currency, JournalEntry, LedgerAccount, PostingSide},
+};
+use chrono::SubsecRound;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AccountBalance {
+    pub account: LedgerAccount,
+    pub debit: Amount,
+    pub credit: Amount,
+}
+
+// The debit and credit totals of every account in a currency.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurrencyTotal {
+    pub currency: Currency,
+    pub debit: Amount,
+    pub credit: Amount,
+}
+
+// A client ledger account whose balance doesn't match the stored client account.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LedgerMismatch {
+    pub account: LedgerAccount,
+    // the account's credit balance in the journal and the matching balance of the stored client
+    pub ledger: Amount,
+    pub stored: Amount,
+}
+
+// The debit and credit totals of every ledger account. The journal is consistent if the totals
+// across all accounts are equal in every currency, which entries guarantee on their own, and if
+// the client accounts match the stored clients.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrialBalance {
+    pub accounts: Vec<AccountBalance>,
+    pub totals: Vec<CurrencyTotal>,
+    pub mismatches: Vec<LedgerMismatch>,
+}
+
+impl TrialBalance {
+    pub fn is_balanced(&self) -> bool {
+        self.totals.iter().all(|t| t.debit == t.credit) && self.mismatches.is_empty()
+    }
+}
+
+// How many journal entries are read at once.
+const JOURNAL_BATCH_SIZE: usize = 1000;
+
+#[derive(Default)]
+struct TrialBalanceBuilder {
+    accounts: BTreeMap<LedgerAccount, AccountBalance>,
+    totals: BTreeMap<Currency, CurrencyTotal>,
+}
+
+impl TrialBalanceBuilder {
+    fn add(&mut self, entry: &JournalEntry) -> Result<(), AmountError> {
+        for posting in &entry.postings {
+            let balance = self.accounts.entry(posting.account).or_insert(AccountBalance {
+                account: posting.account,
+                debit: Amount::ZERO,
+                credit: Amount::ZERO,
+            });
+            let currency = posting.account.currency();
+            let total = self.totals.entry(currency).or_insert(CurrencyTotal {
+                currency,
+                debit: Amount::ZERO,
+                credit: Amount::ZERO,
+            });
+            match posting.side {
+                PostingSide::Debit => {
+                    balance.debit = balance.debit.checked_add(posting.amount)?;
+                    total.debit = total.debit.checked_add(posting.amount)?;
+                }
+                PostingSide::Credit => {
+                    balance.credit = balance.credit.checked_add(posting.amount)?;
+                    total.credit = total.credit.checked_add(posting.amount)?;
+                }
+            }
+        }
+        return Ok(());
+    }
+
+    // Reconciles the client ledger accounts, which are what the engine owes its clients, with the
+    // available and held balances of the stored clients.
+    fn finish(self, clients: &[model::Client]) -> Result<TrialBalance, AmountError> {
+        let mut stored = BTreeMap::new();
+        for client in clients {
+            stored.insert(LedgerAccount::ClientAvailable(client.id, client.currency), client.available);
+            stored.insert(LedgerAccount::ClientHeld(client.id, client.currency), client.held);
+        }
+        let mut ledger = BTreeMap::new();
+        for balance in self.accounts.values() {
+            if let LedgerAccount::ClientAvailable(..) | LedgerAccount::ClientHeld(..) = balance.account {
+                ledger.insert(balance.account, balance.credit.checked_sub(balance.debit)?);
+            }
+        }
+
+        let mut mismatches = vec![];
+        for account in stored.keys().chain(ledger.keys()).collect::<BTreeSet<_>>() {
+            let ledger = ledger.get(account).copied().unwrap_or(Amount::ZERO);
+            let stored = stored.get(account).copied().unwrap_or(Amount::ZERO);
+            if ledger != stored {
+                mismatches.push(LedgerMismatch {
+                    account: *account,
+                    ledger,
+                    stored,
+                });
+            }
+        }
+
+        return Ok(TrialBalance {
+            accounts: self.accounts.into_values().collect(),
+            totals: self.totals.into_values().collect(),
+            mismatches,
+        });
+    }
+}
+
+impl Session<'_> {
+    // Totals the postings of the whole journal by account and reconciles them with the stored
+    // clients. The journal is read in batches, so it never has to fit in memory. Transactions
+    // processed while it is read can show up as mismatches.
+    pub async fn trial_balance(&self) -> SanitizedResult<TrialBalance> {
+        let mut builder = TrialBalanceBuilder::default();
+        let mut min = distant_past();
+        let mut batch_size = JOURNAL_BATCH_SIZE;
+        loop {
+            let entries = self.sanitize(self.store.get_journal_entries_by_time_range(min, distant_future(), batch_size as i32).await)?;
+            if entries.len() < batch_size {
+                for entry in &entries {
+                    self.sanitize(builder.add(entry))?;
+                }
+                break;
+            }
+
+            // the entries of the batch's last microsecond may go on in the next batch, so they are
+            // read again along with it
+            let last = entries[entries.len() - 1].creation_time.trunc_subsecs(6);
+            if entries[0].creation_time.trunc_subsecs(6) == last {
+                batch_size *= 2;
+                continue;
+            }
+            for entry in entries.iter().filter(|e| e.creation_time.trunc_subsecs(6) < last) {
+                self.sanitize(builder.add(entry))?;
+            }
+            min = last;
+            batch_size = JOURNAL_BATCH_SIZE;
+        }
+
+        let clients = self.get_all_clients().await?;
+        return Ok(self.sanitize(builder.finish(&clients))?);
+    }
+}
+
+// An empty journal entry for the operation that produced the given client revision.
+pub(crate) fn journal_entry(client_revision: &model::Client, transaction: &model::Transaction) -> JournalEntry {
+    return JournalEntry::new(client_revision.id, transaction.tx, transaction.record_type, client_revision.revision_time);
+}
@@ -12,21 +12,42 @@ pub mod transaction;
 pub use transaction::*;
 pub mod process_transaction;
 pub use process_transaction::*;
+pub mod process_csv;
+pub use process_csv::*;
 pub mod client;
 pub use client::*;
 pub mod dispute;
 pub use dispute::*;
+pub mod page;
+pub use page::*;
+pub mod adjustment;
+pub mod event;
+pub mod journal;
+pub use journal::*;
+pub mod verify;
+pub use verify::*;
+pub mod fx;
+pub use fx::*;
+pub mod fee;
+pub use fee::*;
 
+#[derive(Clone)]
 pub struct App {
     config: Config,
     store: store::Store<dynstore::Backend>,
+    fx_rates: RateTable,
 }
 
 impl App {
     pub async fn new_with_config(config: Config) -> Result<App> {
+        config.validate()?;
         let store = store::Store::new_with_config(&config.store)?;
+        let fx_rates = match &config.fx_rates {
+            Some(path) => RateTable::load(path)?,
+            None => RateTable::default(),
+        };
 
-        Ok(Self { store, config })
+        Ok(Self { store, config, fx_rates })
     }
 
     pub fn new_session(&self, logger: slog::Logger) -> Session {
@@ -40,6 +61,10 @@ impl App {
     pub fn config(&self) -> &Config {
         &self.config
     }
+
+    pub fn fx_rates(&self) -> &RateTable {
+        &self.fx_rates
+    }
 }
 
 #[cfg(test)]
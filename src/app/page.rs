@@ -0,0 +1,279 @@
+use crate::{
+    app::{distant_future, distant_past, SanitizedResult},
+    model::Id,
+};
+use chrono::{DateTime, Duration, SecondsFormat, SubsecRound, Utc};
+use std::{cmp::Ordering, fmt, future::Future, str::FromStr};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
+
+// A position in a time scored set. Members with the same score are ordered by id in the store, so
+// the id tells apart members that share the cursor's microsecond. A cursor without an id is before
+// or after every member of its microsecond, depending on the direction.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PageCursor {
+    pub time: DateTime<Utc>,
+    pub id: Option<Id>,
+}
+
+// Selects a page of a time scored set. The after and before cursors are exclusive bounds.
+#[derive(Clone, Debug, Default)]
+pub struct PageQuery {
+    pub after: Option<PageCursor>,
+    pub before: Option<PageCursor>,
+    pub limit: usize,
+    pub order: SortOrder,
+}
+
+#[derive(Clone, Debug)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    // the cursor to continue from, as after when ascending or before when descending, if there may be more
+    pub next: Option<PageCursor>,
+}
+
+impl PageQuery {
+    // Fetches members in batches until the page is full, since members that don't match the filter
+    // don't count towards the limit. time_of and id_of must return the score and the member the
+    // item is stored with.
+    pub(crate) async fn scan<T, F, Fut>(
+        &self,
+        mut fetch: F,
+        time_of: fn(&T) -> DateTime<Utc>,
+        id_of: fn(&T) -> Id,
+        filter: impl Fn(&T) -> bool,
+    ) -> SanitizedResult<Page<T>>
+    where
+        F: FnMut(DateTime<Utc>, DateTime<Utc>, i32) -> Fut,
+        Fut: Future<Output = SanitizedResult<Vec<T>>>,
+    {
+        let mut items = Vec::with_capacity(self.limit);
+        if self.limit == 0 {
+            return Ok(Page { items, next: None });
+        }
+
+        // the cursor in the scan direction moves along with the scan, the other one is fixed
+        let (mut cursor, bound) = match self.order {
+            SortOrder::Ascending => (self.after.clone(), self.before.as_ref()),
+            SortOrder::Descending => (self.before.clone(), self.after.as_ref()),
+        };
+        let mut batch_size = self.limit;
+
+        loop {
+            // time ranges are inclusive, the members of the cursors' microseconds that are out of
+            // the page are skipped below
+            let min = match self.order {
+                SortOrder::Ascending => cursor.as_ref().map(|c| c.min_time()),
+                SortOrder::Descending => bound.map(|c| c.min_time()),
+            };
+            let max = match self.order {
+                SortOrder::Ascending => bound.map(|c| c.max_time()),
+                SortOrder::Descending => cursor.as_ref().map(|c| c.max_time()),
+            };
+            let (min, max) = (min.unwrap_or_else(distant_past), max.unwrap_or_else(distant_future));
+            if min > max {
+                break;
+            }
+
+            let limit = match self.order {
+                SortOrder::Ascending => batch_size as i32,
+                SortOrder::Descending => -(batch_size as i32),
+            };
+            let scanned = fetch(min, max, limit).await?;
+            let exhausted = scanned.len() < batch_size;
+            let mut moved = false;
+            for item in scanned {
+                // scores only have microsecond resolution
+                let (time, id) = (time_of(&item).trunc_subsecs(6), id_of(&item));
+                if cursor.as_ref().map_or(false, |c| self.order.position(c, time, &id) != Ordering::Greater) {
+                    continue;
+                }
+                if bound.map_or(false, |b| self.order.position(b, time, &id) != Ordering::Less) {
+                    return Ok(Page { items, next: None });
+                }
+                cursor = Some(PageCursor { time, id: Some(id) });
+                moved = true;
+                if !filter(&item) {
+                    continue;
+                }
+
+                items.push(item);
+                if items.len() == self.limit {
+                    return Ok(Page { items, next: cursor });
+                }
+            }
+            if exhausted {
+                break;
+            }
+            // a batch is refetched from the cursor's microsecond, if it only had members that were
+            // already scanned there are more of them than the batch size
+            batch_size = match moved {
+                true => self.limit,
+                false => batch_size * 2,
+            };
+        }
+
+        return Ok(Page { items, next: None });
+    }
+}
+
+impl PageCursor {
+    // The earliest time of the members after the cursor.
+    fn min_time(&self) -> DateTime<Utc> {
+        match self.id {
+            Some(_) => self.time,
+            None => self.time + Duration::microseconds(1),
+        }
+    }
+
+    // The latest time of the members before the cursor.
+    fn max_time(&self) -> DateTime<Utc> {
+        match self.id {
+            Some(_) => self.time,
+            None => self.time - Duration::microseconds(1),
+        }
+    }
+
+    // Compares a member to the cursor in ascending order.
+    fn cmp_member(&self, time: DateTime<Utc>, id: &Id) -> Ordering {
+        return time.cmp(&self.time).then_with(|| match &self.id {
+            Some(cursor_id) => id.as_bytes().cmp(cursor_id.as_bytes()),
+            // the microsecond of a cursor without an id is skipped as a whole in both directions
+            None => Ordering::Equal,
+        });
+    }
+}
+
+impl SortOrder {
+    // Where a member is relative to the cursor in this order, Greater if it comes after it.
+    fn position(&self, cursor: &PageCursor, time: DateTime<Utc>, id: &Id) -> Ordering {
+        return match self {
+            SortOrder::Ascending => cursor.cmp_member(time, id),
+            SortOrder::Descending => cursor.cmp_member(time, id).reverse(),
+        };
+    }
+}
+
+// Cursors are formatted as their time, followed by a comma and the hex id if they have one.
+impl FromStr for PageCursor {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (time, id) = match s.split_once(',') {
+            Some((time, id)) => (time, Some(id.parse()?)),
+            None => (s, None),
+        };
+        let time: DateTime<Utc> = time.parse().map_err(|err| format!("invalid cursor time {}: {}", time, err))?;
+        return Ok(PageCursor {
+            time: time.trunc_subsecs(6),
+            id,
+        });
+    }
+}
+
+impl fmt::Display for PageCursor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.time.to_rfc3339_opts(SecondsFormat::Micros, true))?;
+        if let Some(id) = &self.id {
+            write!(f, ",{}", id)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "asc" => Ok(SortOrder::Ascending),
+            "desc" => Ok(SortOrder::Descending),
+            _ => Err(format!("unknown sort order {}, expected asc or desc", s)),
+        }
+    }
+}
+
+impl fmt::Display for SortOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SortOrder::Ascending => write!(f, "asc"),
+            SortOrder::Descending => write!(f, "desc"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Pages through members the way the store returns them, ordered by score and then by id.
+    async fn scan_all(members: &[(DateTime<Utc>, Id)], limit: usize, order: SortOrder, filter: fn(&(DateTime<Utc>, Id)) -> bool) -> Vec<(DateTime<Utc>, Id)> {
+        let fetch = |min: DateTime<Utc>, max: DateTime<Utc>, limit: i32| {
+            let mut scanned = members.iter().filter(|(t, _)| *t >= min && *t <= max).cloned().collect::<Vec<_>>();
+            scanned.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.1.as_bytes().cmp(b.1.as_bytes())));
+            if limit < 0 {
+                scanned.reverse();
+            }
+            scanned.truncate(limit.unsigned_abs() as usize);
+            async move { Ok(scanned) }
+        };
+
+        let mut page = PageQuery {
+            limit,
+            order,
+            ..Default::default()
+        };
+        let mut ret = vec![];
+        loop {
+            let result = page.scan(fetch, |m| m.0, |m| m.1.clone(), filter).await.unwrap();
+            assert!(result.items.len() <= limit);
+            ret.extend(result.items);
+            match (result.next, order) {
+                (None, _) => return ret,
+                (next, SortOrder::Ascending) => page.after = next,
+                (next, SortOrder::Descending) => page.before = next,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan() {
+        // most members share a microsecond with others
+        let t = |us: i64| "2020-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap() + Duration::microseconds(us);
+        let mut members = vec![];
+        for (i, us) in [0, 0, 0, 0, 0, 1, 2, 2, 2, 3].into_iter().enumerate() {
+            members.push((t(us), Id::from(i as u32 + 1)));
+        }
+
+        for limit in 1..=4 {
+            assert_eq!(scan_all(&members, limit, SortOrder::Ascending, |_| true).await, members);
+            let mut reversed = members.clone();
+            reversed.reverse();
+            assert_eq!(scan_all(&members, limit, SortOrder::Descending, |_| true).await, reversed);
+
+            let even = |m: &(DateTime<Utc>, Id)| m.1.as_bytes()[3] % 2 == 0;
+            let expected = members.iter().filter(|m| even(m)).cloned().collect::<Vec<_>>();
+            assert_eq!(scan_all(&members, limit, SortOrder::Ascending, even).await, expected);
+        }
+
+        // a cursor without an id skips its whole microsecond
+        let page = PageQuery {
+            after: Some("2020-01-01T00:00:00Z".parse().unwrap()),
+            before: Some(format!("2020-01-01T00:00:00.000002Z,{}", Id::from(8)).parse().unwrap()),
+            limit: 10,
+            order: SortOrder::Ascending,
+        };
+        let fetch = |min: DateTime<Utc>, max: DateTime<Utc>, _| {
+            let scanned = members.iter().filter(|(t, _)| *t >= min && *t <= max).cloned().collect::<Vec<_>>();
+            async move { Ok(scanned) }
+        };
+        let result = page.scan(fetch, |m| m.0, |m| m.1.clone(), |_| true).await.unwrap();
+        assert_eq!(result.items, members[5..7].to_vec());
+        assert_eq!(result.next, None);
+    }
+}
@@ -0,0 +1,371 @@
+use crate::{
+    app::{RejectionReason, SanitizedError, Session},
+    model::{self, CSVClient},
+    Result,
+};
+use chrono::{DateTime, Utc};
+use flate2::read::MultiGzDecoder;
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    runtime::Handle,
+    sync::mpsc,
+    task::JoinHandle,
+};
+
+// The file path that makes process_csv read from stdin.
+pub const STDIN_PATH: &str = "-";
+
+// How many parsed records can be buffered ahead of processing, in total and per worker.
+const CSV_CHANNEL_CAPACITY: usize = 1024;
+const CSV_WORKER_CHANNEL_CAPACITY: usize = 256;
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+// A parsed CSV record, or the error it couldn't be parsed with, along with the line it was read
+// from.
+type CSVRecord = (u64, std::result::Result<model::CSVTransaction, csv::Error>);
+
+// One row of the rejection report, written for every input record that was skipped. The tx,
+// client and type are unset for records that couldn't be parsed.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct CSVRejection {
+    pub line: u64,
+    pub tx: Option<u32>,
+    pub client: Option<u16>,
+    #[serde(rename = "type")]
+    pub record_type: Option<model::RecordType>,
+    pub reason: RejectionReason,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejectionFormat {
+    CSV,
+    JSONL,
+}
+
+impl RejectionFormat {
+    // Picks the format from a file extension, anything but .jsonl is written as CSV.
+    pub fn from_path(path: &str) -> Self {
+        if path.ends_with(".jsonl") {
+            return RejectionFormat::JSONL;
+        }
+        return RejectionFormat::CSV;
+    }
+}
+
+pub enum RejectionWriter {
+    CSV(csv::Writer<Box<dyn Write + Send>>),
+    JSONL(io::BufWriter<Box<dyn Write + Send>>),
+}
+
+impl RejectionWriter {
+    pub fn new<W: Write + Send + 'static>(writer: W, format: RejectionFormat) -> Self {
+        let writer: Box<dyn Write + Send> = Box::new(writer);
+        match format {
+            RejectionFormat::CSV => RejectionWriter::CSV(csv::WriterBuilder::new().from_writer(writer)),
+            RejectionFormat::JSONL => RejectionWriter::JSONL(io::BufWriter::new(writer)),
+        }
+    }
+
+    pub fn write(&mut self, rejection: &CSVRejection) -> Result<()> {
+        match self {
+            RejectionWriter::CSV(wtr) => wtr.serialize(rejection)?,
+            RejectionWriter::JSONL(wtr) => {
+                serde_json::to_writer(&mut *wtr, rejection)?;
+                wtr.write_all(b"\n")?;
+            }
+        }
+        return Ok(());
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        match self {
+            RejectionWriter::CSV(wtr) => wtr.flush()?,
+            RejectionWriter::JSONL(wtr) => wtr.flush()?,
+        }
+        return Ok(());
+    }
+}
+
+impl Session<'_> {
+    pub async fn process_csv(&self, file_path: String, rejections: Option<RejectionWriter>) -> Result<()> {
+        if file_path == STDIN_PATH {
+            return self.process_csv_reader(io::stdin(), rejections).await;
+        }
+        return self.process_csv_reader(File::open(file_path)?, rejections).await;
+    }
+
+    pub async fn process_csv_async_reader<R: AsyncRead + Unpin + Send + 'static>(&self, reader: R, rejections: Option<RejectionWriter>) -> Result<()> {
+        let handle = Handle::current();
+        return self.process_csv_reader(BlockingReader { inner: reader, handle }, rejections).await;
+    }
+
+    // Processes CSV transactions from any reader. Gzip compressed input is detected and
+    // decompressed on the fly. Records are parsed on a blocking thread and streamed through a
+    // bounded channel, so the input never has to fit in memory. Every skipped record is reported
+    // to the rejections writer, if any.
+    pub async fn process_csv_reader<R: Read + Send + 'static>(&self, reader: R, rejections: Option<RejectionWriter>) -> Result<()> {
+        let (sender, receiver) = mpsc::channel(CSV_CHANNEL_CAPACITY);
+        let parser = tokio::task::spawn_blocking(move || read_csv_transactions(reader, sender));
+
+        let (rejection_sender, reporter) = match rejections {
+            Some(writer) => {
+                let (rejection_sender, rejection_receiver) = mpsc::channel(CSV_CHANNEL_CAPACITY);
+                let reporter = tokio::task::spawn_blocking(move || write_rejections(rejection_receiver, writer));
+                (Some(rejection_sender), Some(reporter))
+            }
+            None => (None, None),
+        };
+
+        match self.app.config().csv_workers {
+            0 | 1 => self.process_csv_transactions(receiver, rejection_sender).await?,
+            workers => self.process_csv_transactions_sharded(receiver, rejection_sender, workers).await?,
+        }
+
+        if let Some(reporter) = reporter {
+            reporter.await??;
+        }
+        return parser.await?;
+    }
+
+    async fn process_csv_transactions(&self, mut receiver: mpsc::Receiver<CSVRecord>, rejections: Option<mpsc::Sender<CSVRejection>>) -> Result<()> {
+        while let Some(record) = receiver.recv().await {
+            if !self.process_csv_record(record, &rejections).await? {
+                // the report writer stopped because of an error, which is reported by process_csv_reader
+                break;
+            }
+        }
+
+        return Ok(());
+    }
+
+    // Processes a single record and reports it if it is rejected. Returns false if the report
+    // writer has stopped.
+    async fn process_csv_record(&self, (line, csv_transaction): CSVRecord, rejections: &Option<mpsc::Sender<CSVRejection>>) -> Result<bool> {
+        let csv_transaction = match csv_transaction {
+            Ok(csv_transaction) => csv_transaction,
+            Err(err) => {
+                info!(self.logger(), "{}: {}", RejectionReason::MalformedRecord, err; "line" => line);
+                let rejection = CSVRejection {
+                    line,
+                    tx: None,
+                    client: None,
+                    record_type: None,
+                    reason: RejectionReason::MalformedRecord,
+                };
+                return Ok(report_rejection(rejections, rejection).await);
+            }
+        };
+
+        let transaction = model::Transaction::from(csv_transaction);
+        let reason = match self.process_transaction(transaction).await {
+            Ok(()) => return Ok(true),
+            Err(SanitizedError::Rejected(reason)) => reason,
+            Err(SanitizedError::TransactionIdConflict(_)) => RejectionReason::TransactionIdConflict,
+            Err(err) => return Err(Box::new(err)),
+        };
+        info!(self.logger(), "{reason:}"; "client_id" => transaction.client, "tx_id" => transaction.tx, "line" => line);
+
+        let rejection = CSVRejection {
+            line,
+            tx: Some(transaction.tx),
+            client: Some(transaction.client),
+            record_type: Some(transaction.record_type),
+            reason,
+        };
+        return Ok(report_rejection(rejections, rejection).await);
+    }
+
+    // Ordering only matters per client, so records are hashed by their client column onto worker
+    // tasks that each process their share sequentially. Disputes, resolves and chargebacks are only
+    // accepted from the client that owns the referenced tx, so they go to that client's worker too.
+    // Tx ids are shared by every client though, so the ones deposits, withdrawals and conversions
+    // introduce are claimed in the store in file order before the record is sent off. A record
+    // reusing an id another client claimed first is rejected as a conflict right away, whichever
+    // worker would have gotten to it first.
+    async fn process_csv_transactions_sharded(
+        &self,
+        mut receiver: mpsc::Receiver<CSVRecord>,
+        rejections: Option<mpsc::Sender<CSVRejection>>,
+        workers: usize,
+    ) -> Result<()> {
+        let (senders, handles) = self.spawn_csv_workers(&rejections, workers);
+
+        while let Some(record) = receiver.recv().await {
+            let csv_transaction = match &record.1 {
+                Ok(csv_transaction) => *csv_transaction,
+                Err(_) => {
+                    // malformed records don't touch any account, they are only reported
+                    if !self.process_csv_record(record, &rejections).await? {
+                        break;
+                    }
+                    continue;
+                }
+            };
+            if let model::RecordType::Deposit | model::RecordType::Withdrawal | model::RecordType::Conversion = csv_transaction.record_type {
+                if self.store.claim_transaction_id(csv_transaction.tx, csv_transaction.client).await? != csv_transaction.client {
+                    if !self.reject_csv_tx_id_conflict(record.0, csv_transaction, &rejections).await? {
+                        break;
+                    }
+                    continue;
+                }
+            }
+
+            let shard = csv_transaction.client as usize % workers;
+            if senders[shard].send(record).await.is_err() {
+                // the worker stopped because of an error, which is reported below
+                break;
+            }
+        }
+
+        return join_csv_workers((senders, handles)).await;
+    }
+
+    // Rejects a record whose tx id another client claimed first. It is recorded in the dedup index
+    // like any other conflicting resubmission.
+    async fn reject_csv_tx_id_conflict(
+        &self,
+        line: u64,
+        csv_transaction: model::CSVTransaction,
+        rejections: &Option<mpsc::Sender<CSVRejection>>,
+    ) -> Result<bool> {
+        let transaction = model::Transaction::from(csv_transaction);
+        self.store
+            .add_duplicate_transaction(&model::DuplicateTransaction::new(transaction, true))
+            .await?;
+        let reason = RejectionReason::TransactionIdConflict;
+        info!(self.logger(), "{reason:}"; "client_id" => transaction.client, "tx_id" => transaction.tx, "line" => line);
+
+        let rejection = CSVRejection {
+            line,
+            tx: Some(transaction.tx),
+            client: Some(transaction.client),
+            record_type: Some(transaction.record_type),
+            reason,
+        };
+        return Ok(report_rejection(rejections, rejection).await);
+    }
+
+    fn spawn_csv_workers(&self, rejections: &Option<mpsc::Sender<CSVRejection>>, workers: usize) -> CSVWorkers {
+        let mut senders = Vec::with_capacity(workers);
+        let mut handles = Vec::with_capacity(workers);
+        for _ in 0..workers {
+            let (sender, worker_receiver) = mpsc::channel(CSV_WORKER_CHANNEL_CAPACITY);
+            let app = self.app.clone();
+            let store = self.store.clone();
+            let logger = self.logger();
+            let rejections = rejections.clone();
+            handles.push(tokio::spawn(async move {
+                let sess = Session {
+                    app: &app,
+                    store,
+                    inner_logger: logger,
+                };
+                sess.process_csv_transactions(worker_receiver, rejections).await
+            }));
+            senders.push(sender);
+        }
+        return (senders, handles);
+    }
+
+    pub async fn output_all_accounts(&self) -> Result<()> {
+        return output_accounts(self.get_all_clients().await?);
+    }
+
+    // Outputs the accounts as they were at the given time.
+    pub async fn output_all_accounts_as_of(&self, time: DateTime<Utc>) -> Result<()> {
+        return output_accounts(self.get_all_clients_as_of(time).await?);
+    }
+}
+
+// Sends the rejection to the report writer, if any. Returns false if the writer has stopped.
+async fn report_rejection(rejections: &Option<mpsc::Sender<CSVRejection>>, rejection: CSVRejection) -> bool {
+    return match rejections {
+        Some(rejections) => rejections.send(rejection).await.is_ok(),
+        None => true,
+    };
+}
+
+// The record senders of the CSV workers and the tasks running them.
+type CSVWorkers = (Vec<mpsc::Sender<CSVRecord>>, Vec<JoinHandle<Result<()>>>);
+
+// Waits for the workers to process every record they were sent.
+async fn join_csv_workers((senders, handles): CSVWorkers) -> Result<()> {
+    drop(senders);
+    for handle in handles {
+        handle.await??;
+    }
+    return Ok(());
+}
+
+fn output_accounts(clients: Vec<model::Client>) -> Result<()> {
+    let mut wtr = csv::WriterBuilder::new().from_writer(io::stdout());
+    for client in clients {
+        wtr.serialize(CSVClient::from(client))?;
+    }
+    wtr.flush()?;
+
+    return Ok(());
+}
+
+fn read_csv_transactions<R: Read + Send + 'static>(reader: R, sender: mpsc::Sender<CSVRecord>) -> Result<()> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(decompressed(reader)?);
+
+    let mut raw_record = csv::ByteRecord::new();
+    let headers = rdr.byte_headers()?.clone();
+
+    while rdr.read_byte_record(&mut raw_record)? {
+        // a record that can't be parsed is reported as rejected instead of stopping the processing
+        let csv_transaction = raw_record.deserialize::<model::CSVTransaction>(Some(&headers));
+        // csv miscounts lines on CRLF input, transaction records never span multiple lines so the
+        // line number is derived from the record index instead, the header being on line 1
+        let line = raw_record.position().map(|p| p.record() + 1).unwrap_or_default();
+        if sender.blocking_send((line, csv_transaction)).is_err() {
+            // processing stopped early, the error is reported on the receiving side
+            break;
+        }
+    }
+
+    return Ok(());
+}
+
+fn write_rejections(mut receiver: mpsc::Receiver<CSVRejection>, mut writer: RejectionWriter) -> Result<()> {
+    while let Some(rejection) = receiver.blocking_recv() {
+        writer.write(&rejection)?;
+    }
+    return writer.flush();
+}
+
+// Peeks at the first bytes of the input and wraps it in a gzip decoder if needed.
+fn decompressed<R: Read + Send + 'static>(mut reader: R) -> io::Result<Box<dyn Read + Send>> {
+    let mut magic = Vec::with_capacity(GZIP_MAGIC.len());
+    (&mut reader).take(GZIP_MAGIC.len() as u64).read_to_end(&mut magic)?;
+
+    let is_gzip = magic == GZIP_MAGIC;
+    let reader = Read::chain(io::Cursor::new(magic), reader);
+    if is_gzip {
+        return Ok(Box::new(MultiGzDecoder::new(reader)));
+    }
+    return Ok(Box::new(reader));
+}
+
+// Adapts an AsyncRead so that it can be consumed from a blocking thread.
+struct BlockingReader<R> {
+    inner: R,
+    handle: Handle,
+}
+
+impl<R: AsyncRead + Unpin> Read for BlockingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.handle.block_on(self.inner.read(buf))
+    }
+}
@@ -1,57 +1,53 @@
 use crate::{
-    app::{SanitizedError, SanitizedResult, Session},
-    model::{self, CSVClient},
-    Result,
+    app::{journal_entry, NegativeBalancePolicy, RejectionReason, SanitizedError, SanitizedResult, Session, WithdrawalDisputePolicy},
+    model,
 };
-use std::io;
 
 impl Session<'_> {
-    pub async fn process_csv(&self, file_path: String) -> Result<()> {
-        let mut rdr = csv::ReaderBuilder::new()
-            .has_headers(true)
-            .trim(csv::Trim::All)
-            .flexible(true)
-            .from_path(file_path)?;
-
-        let mut raw_record = csv::ByteRecord::new();
-        let headers = rdr.byte_headers()?.clone();
-
-        while rdr.read_byte_record(&mut raw_record)? {
-            let csv_transaction: model::CSVTransaction = raw_record.deserialize(Some(&headers))?;
-            let transaction = model::Transaction::from(csv_transaction);
-            match self.process_transaction(transaction).await {
-                Err(SanitizedError::UserError(msg)) => {
-                    info!(self.logger(), "{msg:}"; "client_id" => transaction.client, "tx_id" => transaction.tx);
-                }
-                Err(err) => return Err(Box::new(err)),
-                Ok(()) => {}
-            };
+    pub async fn process_transaction(&self, mut transaction: model::Transaction) -> SanitizedResult<()> {
+        let client_id = transaction.client;
+        debug!(self.logger(), "{:?}", transaction; "client_id" => transaction.client, "tx_id" => transaction.tx);
+        if transaction.record_type.is_adjustment() {
+            return Err(SanitizedError::Rejected(RejectionReason::AdminOnly));
         }
-
-        return Ok(());
-    }
-
-    pub async fn output_all_accounts(&self) -> Result<()> {
-        let all_clients = self.get_all_clients().await?;
-        let mut wtr = csv::WriterBuilder::new().from_writer(io::stdout());
-        for client in all_clients {
-            wtr.serialize(CSVClient::from(client))?;
+        if transaction.record_type == model::RecordType::Fee {
+            return Err(SanitizedError::Rejected(RejectionReason::EngineOnly));
         }
-        wtr.flush()?;
 
-        return Ok(());
-    }
+        for _ in 0..3i32 {
+            // Deposits and withdrawals introduce new tx ids, anything else references an existing one.
+            // This is checked on every attempt since contention may come from the same id being
+            // processed concurrently.
+            if let model::RecordType::Deposit | model::RecordType::Withdrawal | model::RecordType::Conversion = transaction.record_type {
+                if let Some(existing) = self.get_transaction_by_id(transaction.tx).await? {
+                    return self.duplicate_transaction(existing, transaction).await;
+                }
+            }
 
-    pub async fn process_transaction(&self, transaction: model::Transaction) -> SanitizedResult<()> {
-        let client_id = transaction.client;
-        debug!(self.logger(), "{:?}", transaction; "client_id" => transaction.client, "tx_id" => transaction.tx);
+            // disputes, resolves and chargebacks apply to the account the referenced tx was made in.
+            // If it doesn't exist the dispute is rejected later on.
+            if !matches!(
+                transaction.record_type,
+                model::RecordType::Deposit | model::RecordType::Withdrawal | model::RecordType::Conversion
+            ) {
+                if let Some(reference_tx) = self.get_transaction_by_id(transaction.tx).await? {
+                    transaction.currency = reference_tx.currency;
+                }
+            }
 
-        for _ in 0..3i32 {
-            let client = match self.get_client_by_id(client_id as _).await? {
-                Some(c) if c.locked => return Err(SanitizedError::UserError("client is locked".to_string())),
+            // the write fails with contention if the lock changes in the meantime
+            let lock = self.get_client_lock(client_id).await?;
+            if lock.locked && !self.app.config().locked_clients.allows(transaction.record_type) {
+                return Err(SanitizedError::Rejected(RejectionReason::ClientLocked));
+            }
+
+            let client = match self.get_client_by_id(client_id, transaction.currency).await? {
+                Some(c) if c.locked && !self.app.config().locked_clients.allows(transaction.record_type) => {
+                    return Err(SanitizedError::Rejected(RejectionReason::ClientLocked));
+                }
                 Some(c) => c,
                 None => {
-                    let new_client = model::Client::new(client_id, None);
+                    let new_client = model::Client::new(client_id, transaction.currency, None);
                     match self.add_client(&new_client).await {
                         Ok(_) => new_client,
                         Err(SanitizedError::Contention) => continue,
@@ -61,123 +57,399 @@ impl Session<'_> {
             };
 
             let res = match transaction.record_type {
-                model::RecordType::Deposit => self.deposit(client, transaction).await,
-                model::RecordType::Withdrawal => self.withdrawal(client, transaction).await,
-                model::RecordType::Dispute => self.dispute(client, transaction).await,
-                model::RecordType::Resolve => self.resolve(client, transaction).await,
-                model::RecordType::Chargeback => self.chargeback(client, transaction).await,
+                model::RecordType::Deposit => self.deposit(client, &lock, transaction).await,
+                model::RecordType::Withdrawal => self.withdrawal(client, &lock, transaction).await,
+                model::RecordType::Dispute => self.dispute(client, &lock, transaction).await,
+                model::RecordType::Resolve => self.resolve(client, &lock, transaction).await,
+                model::RecordType::Chargeback => self.chargeback(client, &lock, transaction).await,
+                model::RecordType::Conversion => self.conversion(client, &lock, transaction).await,
+                model::RecordType::Fee | model::RecordType::Lock | model::RecordType::Unlock | model::RecordType::Credit | model::RecordType::Debit => {
+                    unreachable!("fees and adjustments are rejected before processing")
+                }
             };
 
             match res {
                 Ok(_) => return Ok(()),
                 Err(SanitizedError::Contention) => continue,
-                Err(SanitizedError::UserError(_)) => return res,
+                Err(SanitizedError::Rejected(_)) => return res,
                 Err(err) => return Err(err),
             }
         }
-        return Err(SanitizedError::UserError("transaction contention".to_string()));
+        return Err(SanitizedError::Rejected(RejectionReason::Contention));
     }
 
-    pub async fn deposit(&self, client: model::Client, transaction: model::Transaction) -> SanitizedResult<()> {
-        let available = client.available + transaction.amount;
+    // Resubmitting an identical transaction succeeds without side effects, reusing its id for a
+    // different one is a conflict. Both get recorded in the dedup index.
+    pub(crate) async fn duplicate_transaction(&self, existing: model::Transaction, transaction: model::Transaction) -> SanitizedResult<()> {
+        let is_conflicting = !existing.is_same_operation(&transaction);
+        let duplicate = model::DuplicateTransaction::new(transaction, is_conflicting);
+        self.sanitize(self.store.add_duplicate_transaction(&duplicate).await)?;
 
-        let client_revision = client.with_patch(model::ClientPatch {
+        if is_conflicting {
+            return Err(SanitizedError::TransactionIdConflict(transaction.tx));
+        }
+        info!(self.logger(), "tx has already been processed, skipping"; "client_id" => transaction.client, "tx_id" => transaction.tx);
+        return Ok(());
+    }
+
+    // The deposit's fee is taken out of the deposited funds, deposits smaller than their fee are rejected.
+    pub async fn deposit(&self, client: model::Client, lock: &model::ClientLock, transaction: model::Transaction) -> SanitizedResult<()> {
+        if transaction.amount.is_zero() || transaction.amount.is_negative() {
+            return Err(SanitizedError::Rejected(RejectionReason::InvalidAmount));
+        }
+        let (fee_amount, fee) = self.fee(&transaction, transaction.amount)?;
+        if fee_amount > transaction.amount {
+            return Err(SanitizedError::Rejected(RejectionReason::FeeExceedsAmount));
+        }
+        let available = self.sanitize(client.available.checked_add(transaction.amount).and_then(|a| a.checked_sub(fee_amount)))?;
+
+        let client_revision = self.sanitize(client.clone().with_patch(model::ClientPatch {
             available: Some(available),
+            fees: Some(self.sanitize(client.fees.checked_add(fee_amount))?),
             ..Default::default()
-        });
+        }))?;
 
-        return self.sanitize(self.store.process_transaction(&client_revision, &transaction).await);
+        let event = self.sanitize(model::Event::new(&client, &client_revision, transaction.record_type, transaction.tx))?;
+        let entry = journal_entry(&client_revision, &transaction)
+            .transfer(
+                model::LedgerAccount::Settlement(client.currency),
+                model::LedgerAccount::ClientAvailable(client.id, client.currency),
+                transaction.amount,
+            )
+            .transfer(
+                model::LedgerAccount::ClientAvailable(client.id, client.currency),
+                model::LedgerAccount::FeeIncome(client.currency),
+                fee_amount,
+            );
+        return self.sanitize(
+            self.store
+                .process_transaction(&client_revision, &lock.next(lock.locked), &transaction, fee.as_ref(), &event, &entry)
+                .await,
+        );
     }
 
-    pub async fn withdrawal(&self, client: model::Client, transaction: model::Transaction) -> SanitizedResult<()> {
-        let available = client.available - transaction.amount;
-        if available.is_sign_negative() {
-            return Err(SanitizedError::UserError("not enough funds available".to_string()));
+    // The available funds have to cover both the withdrawn amount and its fee.
+    pub async fn withdrawal(&self, client: model::Client, lock: &model::ClientLock, transaction: model::Transaction) -> SanitizedResult<()> {
+        if transaction.amount.is_zero() || transaction.amount.is_negative() {
+            return Err(SanitizedError::Rejected(RejectionReason::InvalidAmount));
+        }
+        let (fee_amount, fee) = self.fee(&transaction, transaction.amount)?;
+        let available = self.sanitize(client.available.checked_sub(transaction.amount).and_then(|a| a.checked_sub(fee_amount)))?;
+        if available.is_negative() {
+            return Err(SanitizedError::Rejected(RejectionReason::InsufficientFunds));
+        }
+
+        let client_revision = self.sanitize(client.clone().with_patch(model::ClientPatch {
+            available: Some(available),
+            fees: Some(self.sanitize(client.fees.checked_add(fee_amount))?),
+            ..Default::default()
+        }))?;
+
+        let event = self.sanitize(model::Event::new(&client, &client_revision, transaction.record_type, transaction.tx))?;
+        let entry = journal_entry(&client_revision, &transaction)
+            .transfer(
+                model::LedgerAccount::ClientAvailable(client.id, client.currency),
+                model::LedgerAccount::Settlement(client.currency),
+                transaction.amount,
+            )
+            .transfer(
+                model::LedgerAccount::ClientAvailable(client.id, client.currency),
+                model::LedgerAccount::FeeIncome(client.currency),
+                fee_amount,
+            );
+        return self.sanitize(
+            self.store
+                .process_transaction(&client_revision, &lock.next(lock.locked), &transaction, fee.as_ref(), &event, &entry)
+                .await,
+        );
+    }
+
+    // Returns the fee charged for a tx, based on the given amount, along with the fee tx recording
+    // it. There is no fee tx if the tx is free.
+    fn fee(&self, transaction: &model::Transaction, amount: model::Amount) -> SanitizedResult<(model::Amount, Option<model::Transaction>)> {
+        let fee_amount = self.sanitize(self.app.config().fees.fee(transaction.record_type, amount))?;
+        if fee_amount.is_zero() {
+            return Ok((fee_amount, None));
+        }
+        return Ok((fee_amount, Some(transaction.fee(fee_amount))));
+    }
+
+    // Debits the amount from the client's account in the tx's currency and credits its value at the
+    // current fx rate to the one in to_currency, in a single write. The rate is recorded on the tx.
+    // The fee is charged in the currency converted from.
+    pub async fn conversion(&self, client: model::Client, lock: &model::ClientLock, mut transaction: model::Transaction) -> SanitizedResult<()> {
+        let to_currency = match transaction.to_currency {
+            Some(currency) if currency != client.currency => currency,
+            _ => return Err(SanitizedError::Rejected(RejectionReason::InvalidConversion)),
+        };
+        if transaction.amount.is_zero() || transaction.amount.is_negative() {
+            return Err(SanitizedError::Rejected(RejectionReason::InvalidAmount));
+        }
+        let quote = match self.app.fx_rates().quote(client.currency, to_currency, transaction.creation_time) {
+            Some(quote) => quote,
+            None => return Err(SanitizedError::Rejected(RejectionReason::FxRateNotFound)),
+        };
+        // amounts too small to be worth anything in the target currency aren't converted
+        let converted_amount = self.sanitize(quote.rate.convert(transaction.amount))?;
+        if converted_amount.is_zero() {
+            return Err(SanitizedError::Rejected(RejectionReason::InvalidAmount));
+        }
+
+        let (fee_amount, fee) = self.fee(&transaction, transaction.amount)?;
+        let available = self.sanitize(client.available.checked_sub(transaction.amount).and_then(|a| a.checked_sub(fee_amount)))?;
+        if available.is_negative() {
+            return Err(SanitizedError::Rejected(RejectionReason::InsufficientFunds));
         }
 
-        let client_revision = client.with_patch(model::ClientPatch {
+        // a new target account is opened by the conversion's write
+        let (target, opened_target) = match self.get_client_by_id(client.id, to_currency).await? {
+            Some(c) => (c, None),
+            None => {
+                let new_client = model::Client::new(client.id, to_currency, None);
+                (new_client.clone(), Some(new_client))
+            }
+        };
+
+        let client_revision = self.sanitize(client.clone().with_patch(model::ClientPatch {
             available: Some(available),
+            fees: Some(self.sanitize(client.fees.checked_add(fee_amount))?),
+            ..Default::default()
+        }))?;
+        let target_revision = self.sanitize(target.clone().with_patch(model::ClientPatch {
+            available: Some(self.sanitize(target.available.checked_add(converted_amount))?),
             ..Default::default()
+        }))?;
+        transaction.conversion = Some(model::Conversion {
+            converted_amount,
+            rate: quote.rate,
+            rate_time: quote.rate_time,
         });
 
-        return self.sanitize(self.store.process_transaction(&client_revision, &transaction).await);
+        let events = [
+            self.sanitize(model::Event::new(&client, &client_revision, transaction.record_type, transaction.tx))?,
+            self.sanitize(model::Event::new(&target, &target_revision, transaction.record_type, transaction.tx))?,
+        ];
+        let entry = journal_entry(&client_revision, &transaction)
+            .transfer(
+                model::LedgerAccount::ClientAvailable(client.id, client.currency),
+                model::LedgerAccount::Exchange(client.currency),
+                transaction.amount,
+            )
+            .transfer(
+                model::LedgerAccount::Exchange(to_currency),
+                model::LedgerAccount::ClientAvailable(client.id, to_currency),
+                converted_amount,
+            )
+            .transfer(
+                model::LedgerAccount::ClientAvailable(client.id, client.currency),
+                model::LedgerAccount::FeeIncome(client.currency),
+                fee_amount,
+            );
+        return self.sanitize(
+            self.store
+                .process_conversion(
+                    &client_revision,
+                    &target_revision,
+                    opened_target.as_ref(),
+                    &lock.next(lock.locked),
+                    &transaction,
+                    fee.as_ref(),
+                    &events,
+                    &entry,
+                )
+                .await,
+        );
     }
 
-    pub async fn dispute(&self, client: model::Client, dispute_tx: model::Transaction) -> SanitizedResult<()> {
+    pub async fn dispute(&self, client: model::Client, lock: &model::ClientLock, dispute_tx: model::Transaction) -> SanitizedResult<()> {
         let reference_tx = match self.get_transaction_by_id(dispute_tx.tx).await? {
             Some(tx) => tx,
             None => {
-                return Err(SanitizedError::UserError("dispute referenced tx does not exist, skipping".to_string()));
+                return Err(SanitizedError::Rejected(RejectionReason::ReferencedTxNotFound));
             }
         };
+        self.check_referenced_tx_owner(&dispute_tx, reference_tx.client, "dispute")?;
 
-        if let Some(d) = self.get_dispute_by_reference_tx_id(dispute_tx.tx).await? {
-            if !d.is_deleted {
-                return Err(SanitizedError::UserError("tx is already under dispute, skipping".to_string()));
+        let dispute_number = match self.get_dispute_by_reference_tx_id(dispute_tx.tx).await? {
+            Some(d) if d.is_open() => return Err(SanitizedError::Rejected(RejectionReason::AlreadyDisputed)),
+            Some(d) => {
+                let redisputes = self.app.config().redisputes;
+                if d.status != model::DisputeStatus::Resolved || !redisputes.allows(d.dispute_number) {
+                    return Err(SanitizedError::Rejected(RejectionReason::RedisputeNotAllowed));
+                }
+                d.dispute_number + 1
             }
-        }
+            None => 1,
+        };
 
-        let available = client.available - reference_tx.amount;
-        let held = client.held + reference_tx.amount;
-        let client_revision = client.with_patch(model::ClientPatch {
+        // the amount held for the dispute, which is what a resolve or chargeback later releases
+        let (available, held_amount) = match reference_tx.record_type {
+            model::RecordType::Deposit => self.deposit_dispute_hold(&client, &reference_tx)?,
+            model::RecordType::Withdrawal => match self.app.config().withdrawal_disputes {
+                WithdrawalDisputePolicy::Reject => {
+                    return Err(SanitizedError::Rejected(RejectionReason::WithdrawalDisputeNotAllowed));
+                }
+                // the withdrawn funds are held on top of what is available until the dispute is settled
+                WithdrawalDisputePolicy::Hold => (client.available, reference_tx.amount),
+            },
+            _ => {
+                return Err(SanitizedError::Rejected(RejectionReason::ReferencedTxNotDisputable));
+            }
+        };
+        let held = self.sanitize(client.held.checked_add(held_amount))?;
+        let client_revision = self.sanitize(client.clone().with_patch(model::ClientPatch {
             available: Some(available),
             held: Some(held),
             ..Default::default()
-        });
+        }))?;
 
-        let dispute = model::Dispute::new(reference_tx);
+        let dispute = model::Dispute::new(reference_tx, dispute_number, held_amount);
 
-        return self.sanitize(self.store.process_dispute(&client_revision, &dispute).await);
+        let event = self.sanitize(model::Event::new(&client, &client_revision, dispute_tx.record_type, dispute_tx.tx))?;
+        // held withdrawn funds haven't come back yet, so they are held against settlement
+        let held_from = match reference_tx.record_type {
+            model::RecordType::Withdrawal => model::LedgerAccount::Settlement(client.currency),
+            _ => model::LedgerAccount::ClientAvailable(client.id, client.currency),
+        };
+        let entry = journal_entry(&client_revision, &dispute_tx).transfer(held_from, model::LedgerAccount::ClientHeld(client.id, client.currency), held_amount);
+        return self.sanitize(
+            self.store
+                .process_dispute(&client_revision, &lock.next(lock.locked), &dispute, &event, &entry)
+                .await,
+        );
     }
 
-    pub async fn resolve(&self, client: model::Client, resolve_tx: model::Transaction) -> SanitizedResult<()> {
-        let dispute = match self.get_dispute_by_reference_tx_id(resolve_tx.tx).await? {
-            Some(d) => {
-                if d.is_deleted {
-                    return Err(SanitizedError::UserError("resolve tx is no longer disputed, skipping".to_string()));
-                }
-                d
+    // Returns the available funds and the amount to hold when disputing a deposit. If some of the
+    // deposit has already been withdrawn, holding all of it would make available negative, which
+    // is what the negative balance policy decides on.
+    fn deposit_dispute_hold(&self, client: &model::Client, deposit: &model::Transaction) -> SanitizedResult<(model::Amount, model::Amount)> {
+        let available = self.sanitize(client.available.checked_sub(deposit.amount))?;
+        if !available.is_negative() {
+            return Ok((available, deposit.amount));
+        }
+
+        match self.app.config().negative_balances {
+            NegativeBalancePolicy::Allow => return Ok((available, deposit.amount)),
+            NegativeBalancePolicy::Reject => return Err(SanitizedError::Rejected(RejectionReason::InsufficientFunds)),
+            NegativeBalancePolicy::CapHold => {
+                let held_amount = client.available.max(model::Amount::ZERO);
+                return Ok((self.sanitize(client.available.checked_sub(held_amount))?, held_amount));
             }
+        }
+    }
+
+    pub async fn resolve(&self, client: model::Client, lock: &model::ClientLock, resolve_tx: model::Transaction) -> SanitizedResult<()> {
+        let dispute = match self.get_dispute_by_reference_tx_id(resolve_tx.tx).await? {
+            Some(d) => d,
             None => {
-                return Err(SanitizedError::UserError("resolve tx is not disputed, skipping".to_string()));
+                return Err(SanitizedError::Rejected(RejectionReason::NotDisputed));
             }
         };
+        self.check_referenced_tx_owner(&resolve_tx, dispute.referenced_tx.client, "resolve")?;
+        let dispute_revision = self.sanitize(dispute.transition(model::DisputeStatus::Resolved, resolve_tx))?;
+        let disputed_tx = dispute_revision.referenced_tx;
+
+        let held_amount = dispute_revision.held_amount;
 
-        let available = client.available + dispute.referenced_tx.amount;
-        let held = client.held - dispute.referenced_tx.amount;
+        // a resolved withdrawal dispute means the withdrawal stands, so the hold is simply released
+        let available = match disputed_tx.record_type {
+            model::RecordType::Withdrawal => client.available,
+            _ => self.sanitize(client.available.checked_add(held_amount))?,
+        };
+        let held = self.sanitize(client.held.checked_sub(held_amount))?;
 
-        let client_revision = client.with_patch(model::ClientPatch {
+        let client_revision = self.sanitize(client.clone().with_patch(model::ClientPatch {
             available: Some(available),
             held: Some(held),
             ..Default::default()
-        });
-        let dispute_revision = dispute.with_patch(true);
+        }))?;
 
-        return self.sanitize(self.store.remove_dispute(&client_revision, &dispute_revision).await);
+        let event = self.sanitize(model::Event::new(&client, &client_revision, resolve_tx.record_type, resolve_tx.tx))?;
+        let released_to = match disputed_tx.record_type {
+            model::RecordType::Withdrawal => model::LedgerAccount::Settlement(client.currency),
+            _ => model::LedgerAccount::ClientAvailable(client.id, client.currency),
+        };
+        let entry =
+            journal_entry(&client_revision, &resolve_tx).transfer(model::LedgerAccount::ClientHeld(client.id, client.currency), released_to, held_amount);
+        return self.sanitize(
+            self.store
+                .remove_dispute(&client_revision, &lock.next(lock.locked), &dispute_revision, None, &event, &entry)
+                .await,
+        );
     }
 
-    pub async fn chargeback(&self, client: model::Client, chargeback_tx: model::Transaction) -> SanitizedResult<()> {
+    pub async fn chargeback(&self, client: model::Client, lock: &model::ClientLock, chargeback_tx: model::Transaction) -> SanitizedResult<()> {
         let dispute = match self.get_dispute_by_reference_tx_id(chargeback_tx.tx).await? {
-            Some(d) => {
-                if d.is_deleted {
-                    return Err(SanitizedError::UserError("chargeback tx is no longer disputed, skipping".to_string()));
-                }
-                d
-            }
+            Some(d) => d,
             None => {
-                return Err(SanitizedError::UserError("chargeback tx is not disputed, skipping".to_string()));
+                return Err(SanitizedError::Rejected(RejectionReason::NotDisputed));
             }
         };
+        self.check_referenced_tx_owner(&chargeback_tx, dispute.referenced_tx.client, "chargeback")?;
+        let dispute_revision = self.sanitize(dispute.transition(model::DisputeStatus::ChargedBack, chargeback_tx))?;
+        let disputed_tx = dispute_revision.referenced_tx;
 
-        let held = client.held - dispute.referenced_tx.amount;
+        let held_amount = dispute_revision.held_amount;
 
-        let client_revision = client.with_patch(model::ClientPatch {
+        // a charged back withdrawal is reversed, so the held funds are credited back to the client.
+        // A charged back deposit only takes back what was held, which may be less than the deposit
+        // if the negative balance policy capped the hold.
+        let available = match disputed_tx.record_type {
+            model::RecordType::Withdrawal => self.sanitize(client.available.checked_add(held_amount))?,
+            _ => client.available,
+        };
+        // the chargeback has already happened, so its fee is charged even if available goes negative
+        let (fee_amount, fee) = self.fee(&chargeback_tx, disputed_tx.amount)?;
+        let available = self.sanitize(available.checked_sub(fee_amount))?;
+        let held = self.sanitize(client.held.checked_sub(held_amount))?;
+
+        let client_revision = self.sanitize(client.clone().with_patch(model::ClientPatch {
+            available: Some(available),
             held: Some(held),
             locked: Some(true),
-            ..Default::default()
-        });
-        let dispute_revision = dispute.with_patch(true);
+            fees: Some(self.sanitize(client.fees.checked_add(fee_amount))?),
+        }))?;
+
+        let event = self.sanitize(model::Event::new(&client, &client_revision, chargeback_tx.record_type, chargeback_tx.tx))?;
+        // whatever part of a charged back deposit wasn't held is lost, it has already been spent
+        let entry = match disputed_tx.record_type {
+            model::RecordType::Withdrawal => journal_entry(&client_revision, &chargeback_tx).transfer(
+                model::LedgerAccount::ClientHeld(client.id, client.currency),
+                model::LedgerAccount::ClientAvailable(client.id, client.currency),
+                held_amount,
+            ),
+            _ => journal_entry(&client_revision, &chargeback_tx)
+                .transfer(
+                    model::LedgerAccount::ClientHeld(client.id, client.currency),
+                    model::LedgerAccount::Settlement(client.currency),
+                    held_amount,
+                )
+                .transfer(
+                    model::LedgerAccount::ChargebackLoss(client.currency),
+                    model::LedgerAccount::Settlement(client.currency),
+                    self.sanitize(disputed_tx.amount.checked_sub(held_amount))?,
+                ),
+        }
+        .transfer(
+            model::LedgerAccount::ClientAvailable(client.id, client.currency),
+            model::LedgerAccount::FeeIncome(client.currency),
+            fee_amount,
+        );
+        return self.sanitize(
+            self.store
+                .remove_dispute(&client_revision, &lock.next(true), &dispute_revision, fee.as_ref(), &event, &entry)
+                .await,
+        );
+    }
+
+    // Disputes, resolves and chargebacks only reference a tx by id, so they could otherwise move
+    // funds on behalf of a client that doesn't own the referenced tx.
+    fn check_referenced_tx_owner(&self, transaction: &model::Transaction, owner_client_id: u16, step: &str) -> SanitizedResult<()> {
+        if transaction.client == owner_client_id {
+            return Ok(());
+        }
 
-        return self.sanitize(self.store.remove_dispute(&client_revision, &dispute_revision).await);
+        warn!(self.logger(), "{step:} referenced tx belongs to another client"; "client_id" => transaction.client, "tx_id" => transaction.tx, "owner_client_id" => owner_client_id);
+        return Err(SanitizedError::Rejected(RejectionReason::ClientMismatch));
     }
 }
@@ -1,6 +1,6 @@
 use crate::{
-    app::{distant_future, distant_past, SanitizedResult, Session},
-    model::transaction::Transaction,
+    app::{distant_future, distant_past, Page, PageQuery, SanitizedResult, Session},
+    model::transaction::{DuplicateTransaction, RecordType, Transaction},
 };
 
 impl Session<'_> {
@@ -11,8 +11,25 @@ impl Session<'_> {
     pub async fn get_all_client_transactions(&self, client_id: u16) -> SanitizedResult<Vec<Transaction>> {
         Ok(self.sanitize(
             self.store
-                .get_client_transactions_by_time_range(client_id as u32, distant_future(), distant_past(), 0)
+                .get_client_transactions_by_time_range(client_id as u32, distant_past(), distant_future(), 0)
                 .await,
         )?)
     }
+
+    // Pages through a client's transactions by creation time. If record_types isn't empty, only
+    // transactions of those types are returned.
+    pub async fn get_client_transactions(&self, client_id: u16, page: &PageQuery, record_types: &[RecordType]) -> SanitizedResult<Page<Transaction>> {
+        let fetch = |min, max, limit| async move { self.sanitize(self.store.get_client_transactions_by_time_range(client_id as u32, min, max, limit).await) };
+        let filter = |tx: &Transaction| record_types.is_empty() || record_types.contains(&tx.record_type);
+        return page.scan(fetch, |tx| tx.creation_time, |tx| tx.id(), filter).await;
+    }
+
+    // Returns the fees charged for a tx, oldest first.
+    pub async fn get_transaction_fees(&self, id: u32) -> SanitizedResult<Vec<Transaction>> {
+        Ok(self.sanitize(self.store.get_transaction_fees(id).await)?)
+    }
+
+    pub async fn get_duplicate_transactions_by_tx_id(&self, id: u32) -> SanitizedResult<Vec<DuplicateTransaction>> {
+        Ok(self.sanitize(self.store.get_duplicate_transactions_by_tx_id(id).await)?)
+    }
 }
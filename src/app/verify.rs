@@ -0,0 +1,182 @@
+use crate::{
+    app::{SanitizedResult, Session},
+    model::{self, Amount, AmountError},
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiscrepancyKind {
+    // total isn't available + held
+    TotalMismatch,
+    NegativeHeld,
+    // the stored account doesn't match what its transactions and disputes add up to
+    AvailableMismatch,
+    HeldMismatch,
+    LockedMismatch,
+    FeesMismatch,
+    // the stored account doesn't match the one replayed from the client's events
+    SnapshotMismatch,
+}
+
+// A problem found with a stored client account. Values are formatted so that different kinds of
+// discrepancies can be reported side by side.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientDiscrepancy {
+    pub client: u16,
+    pub currency: model::Currency,
+    pub kind: DiscrepancyKind,
+    pub stored: String,
+    pub expected: String,
+}
+
+impl Session<'_> {
+    // Checks every account of a client against its invariants and recomputes its balances from
+    // the transactions and disputes in the store. Returns None if the client doesn't exist.
+    pub async fn verify_client(&self, id: u16) -> SanitizedResult<Option<Vec<ClientDiscrepancy>>> {
+        let accounts = self.get_client_accounts(id).await?;
+        if accounts.is_empty() {
+            return Ok(None);
+        }
+
+        let mut ret = vec![];
+        for account in accounts {
+            ret.extend(self.verify_account(account).await?);
+        }
+        return Ok(Some(ret));
+    }
+
+    // Verifies every client account in the store.
+    pub async fn verify_all(&self) -> SanitizedResult<Vec<ClientDiscrepancy>> {
+        let mut ret = vec![];
+        for client in self.get_all_clients().await? {
+            ret.extend(self.verify_account(client).await?);
+        }
+        return Ok(ret);
+    }
+
+    // Only the transactions and disputes in the account's currency count towards its balances,
+    // along with the conversions to it.
+    async fn verify_account(&self, client: model::Client) -> SanitizedResult<Vec<ClientDiscrepancy>> {
+        let (id, currency) = (client.id, client.currency);
+        let transactions: Vec<_> = self
+            .get_all_client_transactions(id)
+            .await?
+            .into_iter()
+            .filter(|tx| tx.currency == currency || tx.to_currency == Some(currency))
+            .collect();
+        let disputes: Vec<_> = self
+            .get_client_disputes(id, true)
+            .await?
+            .into_iter()
+            .filter(|d| d.referenced_tx.currency == currency)
+            .collect();
+        let expected = self.sanitize(ExpectedBalances::from_history(currency, &transactions, &disputes))?;
+
+        let mut ret = vec![];
+        let mut check = |kind, stored: String, expected: String| {
+            if stored != expected {
+                ret.push(ClientDiscrepancy {
+                    client: id,
+                    currency,
+                    kind,
+                    stored,
+                    expected,
+                });
+            }
+        };
+        let sum = self.sanitize(client.available.checked_add(client.held))?;
+        check(DiscrepancyKind::TotalMismatch, client.total.to_string(), sum.to_string());
+        if client.held.is_negative() {
+            check(DiscrepancyKind::NegativeHeld, client.held.to_string(), Amount::ZERO.to_string());
+        }
+        check(DiscrepancyKind::AvailableMismatch, client.available.to_string(), expected.available.to_string());
+        check(DiscrepancyKind::HeldMismatch, client.held.to_string(), expected.held.to_string());
+        check(DiscrepancyKind::LockedMismatch, client.locked.to_string(), expected.locked.to_string());
+        check(DiscrepancyKind::FeesMismatch, client.fees.to_string(), expected.fees.to_string());
+
+        // clients processed before the event log existed have no events to replay
+        if let Some(replayed) = self.replay_client(id, currency).await? {
+            check(DiscrepancyKind::SnapshotMismatch, snapshot_summary(&client), snapshot_summary(&replayed));
+        }
+
+        return Ok(ret);
+    }
+}
+
+fn snapshot_summary(client: &model::Client) -> String {
+    format!(
+        "revision {}: {} available, {} held, {} total, locked {}, {} fees",
+        client.revision_number, client.available, client.held, client.total, client.locked, client.fees
+    )
+}
+
+#[derive(Debug, Default)]
+struct ExpectedBalances {
+    available: Amount,
+    held: Amount,
+    locked: bool,
+    fees: Amount,
+}
+
+impl ExpectedBalances {
+    // Folds the transactions and disputes, open or closed, of a client's account in the given
+    // currency into the balances they should have produced. Disputes hold what they recorded as
+    // held, which the negative balance policy may have capped.
+    fn from_history(currency: model::Currency, transactions: &[model::Transaction], disputes: &[model::Dispute]) -> Result<Self, AmountError> {
+        let mut ret = Self::default();
+        // chargebacks lock the client and lock/unlock adjustments toggle it, the latest one wins
+        let mut lock_changes: Vec<(DateTime<Utc>, bool)> = vec![];
+
+        for tx in transactions {
+            match tx.record_type {
+                model::RecordType::Deposit | model::RecordType::Credit => ret.available = ret.available.checked_add(tx.amount)?,
+                model::RecordType::Withdrawal | model::RecordType::Debit => ret.available = ret.available.checked_sub(tx.amount)?,
+                model::RecordType::Lock => lock_changes.push((tx.creation_time, true)),
+                model::RecordType::Unlock => lock_changes.push((tx.creation_time, false)),
+                // conversions debit the account they are made from and credit the one they are made to
+                model::RecordType::Conversion if tx.currency == currency => ret.available = ret.available.checked_sub(tx.amount)?,
+                model::RecordType::Conversion => {
+                    if let Some(conversion) = tx.conversion {
+                        ret.available = ret.available.checked_add(conversion.converted_amount)?;
+                    }
+                }
+                // fees are recorded as their own transactions, in the currency they were charged in
+                model::RecordType::Fee => {
+                    ret.available = ret.available.checked_sub(tx.amount)?;
+                    ret.fees = ret.fees.checked_add(tx.amount)?;
+                }
+                // disputes and their settlements are only recorded as disputes
+                model::RecordType::Dispute | model::RecordType::Resolve | model::RecordType::Chargeback => {}
+            }
+        }
+
+        for dispute in disputes {
+            let is_withdrawal = dispute.referenced_tx.record_type == model::RecordType::Withdrawal;
+            match dispute.status {
+                model::DisputeStatus::Open => {
+                    ret.held = ret.held.checked_add(dispute.held_amount)?;
+                    if !is_withdrawal {
+                        ret.available = ret.available.checked_sub(dispute.held_amount)?;
+                    }
+                }
+                // the hold was released, so resolved disputes leave no trace
+                model::DisputeStatus::Resolved => {}
+                model::DisputeStatus::ChargedBack => {
+                    // charged back withdrawals are credited back, charged back deposits are taken back
+                    ret.available = if is_withdrawal {
+                        ret.available.checked_add(dispute.held_amount)?
+                    } else {
+                        ret.available.checked_sub(dispute.held_amount)?
+                    };
+                    lock_changes.push((dispute.resolution_time.unwrap_or(dispute.revision_time), true));
+                }
+            }
+        }
+
+        lock_changes.sort_by_key(|(time, _)| *time);
+        ret.locked = lock_changes.last().map(|(_, locked)| *locked).unwrap_or(false);
+        return Ok(ret);
+    }
+}
@@ -0,0 +1,34 @@
+use crate::{app, cmd::Config, Result};
+use chrono::{DateTime, Utc};
+use clap::Arg;
+
+pub const CMD_NAME: &str = "accounts";
+pub const AS_OF_ARG_NAME: &str = "as-of";
+
+pub fn cmd<'a>() -> clap::Command<'a> {
+    let as_of_arg = Arg::new(AS_OF_ARG_NAME)
+        .long("as-of")
+        .takes_value(true)
+        .help("output the accounts as they were at this RFC 3339 timestamp instead of their current state");
+
+    return clap::Command::new(CMD_NAME)
+        .about("outputs the client accounts from the configured store in a csv format")
+        .arg(as_of_arg);
+}
+
+pub async fn run(logger: slog::Logger, config: Config, matches: &clap::ArgMatches) -> Result<()> {
+    config.validate()?;
+
+    let app = app::App::new_with_config(config.app).await?;
+    let sess = app.new_session(logger);
+
+    match matches.get_one::<String>(AS_OF_ARG_NAME) {
+        Some(as_of) => {
+            let time = DateTime::parse_from_rfc3339(as_of)?.with_timezone(&Utc);
+            sess.output_all_accounts_as_of(time).await?;
+        }
+        None => sess.output_all_accounts().await?,
+    }
+
+    return Ok(());
+}
@@ -6,9 +6,12 @@ use std::{os::unix::io::AsRawFd, path::Path};
 
 pub mod config;
 use config::*;
+pub mod accounts;
 mod ascii_art;
 pub mod process_csv;
 pub mod serve;
+pub mod trial_balance;
+pub mod verify;
 
 pub async fn exec(logger: slog::Logger, matches: &clap::ArgMatches) -> Result<()> {
     let config_path = if let Ok(config_path) = value_t!(matches, "config", String) {
@@ -36,6 +39,9 @@ pub async fn exec(logger: slog::Logger, matches: &clap::ArgMatches) -> Result<()
 
     match matches.subcommand() {
         Some((serve::CMD_NAME, sub_match)) => serve::run(logger, config, sub_match).await,
+        Some((accounts::CMD_NAME, sub_match)) => accounts::run(logger, config, sub_match).await,
+        Some((verify::CMD_NAME, sub_match)) => verify::run(logger, config, sub_match).await,
+        Some((trial_balance::CMD_NAME, sub_match)) => trial_balance::run(logger, config, sub_match).await,
         None => process_csv::run(logger, config, matches).await,
         Some(_) => unreachable!("match arms should cover all the possible cases"),
     }
@@ -54,8 +60,11 @@ pub async fn set_up_logger_and_exec() -> i32 {
                 .help("read configuration from this file")
                 .takes_value(true),
         )
-        .arg(process_csv::arg())
+        .args(process_csv::args())
         .subcommand(serve::cmd())
+        .subcommand(accounts::cmd())
+        .subcommand(verify::cmd())
+        .subcommand(trial_balance::cmd())
         .get_matches();
 
     let stderr = std::io::stderr();
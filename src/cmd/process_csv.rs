@@ -4,25 +4,54 @@ use crate::{
     store, Result,
 };
 use simple_error::bail;
+use std::fs::File;
 
 pub const ARG_NAME: &str = "input-file";
-pub fn arg<'a>() -> clap::Arg<'a> {
-    clap::Arg::new("input-file")
+pub const WORKERS_ARG_NAME: &str = "workers";
+pub const REJECTIONS_ARG_NAME: &str = "rejections";
+
+pub fn args<'a>() -> Vec<clap::Arg<'a>> {
+    let input_arg = clap::Arg::new(ARG_NAME).help("the transactions csv file to process, optionally gzip compressed. Use - to read from stdin");
+
+    let workers_arg = clap::Arg::new(WORKERS_ARG_NAME)
+        .long("workers")
+        .short('w')
+        .takes_value(true)
+        .help("the number of workers transactions are sharded across by client, defaults to processing them sequentially");
+
+    let rejections_arg = clap::Arg::new(REJECTIONS_ARG_NAME)
+        .long("rejections")
+        .short('r')
+        .takes_value(true)
+        .help("write a report of every rejected transaction to this file, as JSON lines if it ends with .jsonl and CSV otherwise");
+
+    return vec![input_arg, workers_arg, rejections_arg];
 }
 
-pub async fn run(logger: slog::Logger, _config: Config, matches: &clap::ArgMatches) -> Result<()> {
+pub async fn run(logger: slog::Logger, config: Config, matches: &clap::ArgMatches) -> Result<()> {
+    let mut app_config = config.app;
+    if matches.is_present(WORKERS_ARG_NAME) {
+        app_config.csv_workers = clap::value_t!(matches, WORKERS_ARG_NAME, usize)?;
+    }
+
+    // CSV processing always happens in memory, only the engine settings are taken from the config
     let app = App::new_with_config(app::Config {
         store: store::Config {
             in_memory: true,
             ..Default::default()
         },
+        ..app_config
     })
     .await?;
     let sess = app.new_session(logger);
 
     if let Some(file_path) = matches.get_one::<String>(ARG_NAME) {
         info!(sess.logger(), "Processing {:} ...", file_path);
-        sess.process_csv(file_path.clone()).await?;
+        let rejections = match matches.get_one::<String>(REJECTIONS_ARG_NAME) {
+            Some(path) => Some(app::RejectionWriter::new(File::create(path)?, app::RejectionFormat::from_path(path))),
+            None => None,
+        };
+        sess.process_csv(file_path.clone(), rejections).await?;
         sess.output_all_accounts().await?;
         info!(sess.logger(), "DONE");
     } else {
@@ -0,0 +1,55 @@
+use crate::{app, cmd::Config, model, Result};
+use serde::Serialize;
+use simple_error::bail;
+use std::io;
+
+pub const CMD_NAME: &str = "trial-balance";
+
+pub fn cmd<'a>() -> clap::Command<'a> {
+    return clap::Command::new(CMD_NAME).about(
+        "outputs the debit and credit totals of every ledger account from the configured store in a csv format, fails if they don't balance in every currency or the client accounts don't match the stored clients",
+    );
+}
+
+#[derive(Serialize)]
+struct CSVAccountBalance {
+    account: String,
+    debit: model::Amount,
+    credit: model::Amount,
+}
+
+pub async fn run(logger: slog::Logger, config: Config, _matches: &clap::ArgMatches) -> Result<()> {
+    let app = app::App::new_with_config(config.app).await?;
+    let sess = app.new_session(logger);
+    let trial_balance = sess.trial_balance().await?;
+
+    let mut wtr = csv::WriterBuilder::new().from_writer(io::stdout());
+    for balance in &trial_balance.accounts {
+        wtr.serialize(CSVAccountBalance {
+            account: balance.account.to_string(),
+            debit: balance.debit,
+            credit: balance.credit,
+        })?;
+    }
+    for total in &trial_balance.totals {
+        wtr.serialize(CSVAccountBalance {
+            account: format!("total:{}", total.currency),
+            debit: total.debit,
+            credit: total.credit,
+        })?;
+    }
+    wtr.flush()?;
+
+    if let Some(total) = trial_balance.totals.iter().find(|t| t.debit != t.credit) {
+        bail!("{} debits {} don't match credits {}", total.currency, total.debit, total.credit)
+    }
+    if let Some(mismatch) = trial_balance.mismatches.first() {
+        bail!(
+            "{} balance {} doesn't match the stored client's {}",
+            mismatch.account,
+            mismatch.ledger,
+            mismatch.stored
+        )
+    }
+    return Ok(());
+}
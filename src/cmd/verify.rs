@@ -0,0 +1,41 @@
+use crate::{app, cmd::Config, Result};
+use clap::Arg;
+use simple_error::bail;
+use std::io;
+
+pub const CMD_NAME: &str = "verify";
+pub const CLIENT_ARG_NAME: &str = "client";
+
+pub fn cmd<'a>() -> clap::Command<'a> {
+    let client_arg = Arg::new(CLIENT_ARG_NAME).long("client").takes_value(true).help("only verify this client");
+
+    return clap::Command::new(CMD_NAME)
+        .about("checks the client accounts in the configured store against their transaction and dispute history, outputs discrepancies in a csv format")
+        .arg(client_arg);
+}
+
+pub async fn run(logger: slog::Logger, config: Config, matches: &clap::ArgMatches) -> Result<()> {
+    let app = app::App::new_with_config(config.app).await?;
+    let sess = app.new_session(logger);
+
+    let discrepancies = if matches.is_present(CLIENT_ARG_NAME) {
+        let client_id = clap::value_t!(matches, CLIENT_ARG_NAME, u16)?;
+        match sess.verify_client(client_id).await? {
+            Some(discrepancies) => discrepancies,
+            None => bail!("client {} not found", client_id),
+        }
+    } else {
+        sess.verify_all().await?
+    };
+
+    let mut wtr = csv::WriterBuilder::new().from_writer(io::stdout());
+    for discrepancy in &discrepancies {
+        wtr.serialize(discrepancy)?;
+    }
+    wtr.flush()?;
+
+    if !discrepancies.is_empty() {
+        bail!("found {} discrepancies", discrepancies.len())
+    }
+    return Ok(());
+}
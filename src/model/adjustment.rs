@@ -0,0 +1,23 @@
+use crate::model::Transaction;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+// An administrative change to a client's account. The transaction goes into the transaction log
+// like any other, the reason is kept alongside it for auditing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Adjustment {
+    pub transaction: Transaction,
+    pub reason: String,
+
+    pub creation_time: DateTime<Utc>,
+}
+
+impl Adjustment {
+    pub fn new(transaction: Transaction, reason: String) -> Self {
+        return Adjustment {
+            transaction,
+            reason,
+            creation_time: Utc::now(),
+        };
+    }
+}
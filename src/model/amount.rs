@@ -0,0 +1,245 @@
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+// The number of decimal places every amount is stored with.
+pub const AMOUNT_DECIMAL_PLACES: usize = 4;
+
+const AMOUNT_SCALE: i64 = 10_000;
+
+// A fixed-point decimal amount with exactly four decimal places.
+//
+// Human readable formats (CSV, JSON) serialize amounts as decimal strings such as "1.5000" and
+// accept either strings or numbers. JSON numbers are exact as long as they have at most 15
+// significant digits, past that they should be passed as strings. CSV fields are parsed from their
+// raw text with deserialize_csv_amount, so they are always exact. Binary formats (msgpack) store
+// the scaled integer directly.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Amount(i64);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AmountError {
+    Overflow,
+    TooManyDecimalPlaces(String),
+    Invalid(String),
+}
+
+impl std::error::Error for AmountError {}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Overflow => write!(f, "amount overflow"),
+            Self::TooManyDecimalPlaces(s) => write!(f, "amount {} has more than {} decimal places", s, AMOUNT_DECIMAL_PLACES),
+            Self::Invalid(s) => write!(f, "invalid amount {}", s),
+        }
+    }
+}
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+
+    // Creates an amount from its scaled representation, i.e. in units of 0.0001.
+    pub fn from_scaled(v: i64) -> Self {
+        Amount(v)
+    }
+
+    // Returns the amount in units of 0.0001.
+    pub fn scaled(&self) -> i64 {
+        self.0
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+
+    pub fn checked_add(self, rhs: Amount) -> Result<Amount, AmountError> {
+        self.0.checked_add(rhs.0).map(Amount).ok_or(AmountError::Overflow)
+    }
+
+    pub fn checked_sub(self, rhs: Amount) -> Result<Amount, AmountError> {
+        self.0.checked_sub(rhs.0).map(Amount).ok_or(AmountError::Overflow)
+    }
+}
+
+impl From<i32> for Amount {
+    fn from(v: i32) -> Self {
+        Amount(v as i64 * AMOUNT_SCALE)
+    }
+}
+
+impl FromStr for Amount {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        return parse_fixed_point(s, AMOUNT_DECIMAL_PLACES).map(Amount);
+    }
+}
+
+// Parses a signed decimal string exactly into an integer in units of 10^-scale_digits, e.g. "1.5"
+// is 15000 with 4 digits. Trailing zeros don't carry any precision, so "1.50000" parses too.
+pub fn parse_fixed_point(s: &str, scale_digits: usize) -> Result<i64, AmountError> {
+    let (negative, unsigned) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (whole, fraction) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    if (whole.is_empty() && fraction.is_empty()) || !whole.bytes().all(|b| b.is_ascii_digit()) || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(AmountError::Invalid(s.to_string()));
+    }
+
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.len() > scale_digits {
+        return Err(AmountError::TooManyDecimalPlaces(s.to_string()));
+    }
+
+    let whole: i64 = match whole.trim_start_matches('0') {
+        "" => 0,
+        digits => digits.parse().map_err(|_| AmountError::Overflow)?,
+    };
+    let fraction: i64 = match fraction {
+        "" => 0,
+        digits => digits.parse::<i64>().map_err(|_| AmountError::Invalid(s.to_string()))? * 10i64.pow((scale_digits - digits.len()) as u32),
+    };
+
+    let scaled = whole
+        .checked_mul(10i64.pow(scale_digits as u32))
+        .and_then(|v| v.checked_add(fraction))
+        .ok_or(AmountError::Overflow)?;
+    return Ok(if negative { -scaled } else { scaled });
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.is_negative() { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        let scale = AMOUNT_SCALE as u64;
+        write!(f, "{}{}.{:04}", sign, abs / scale, abs % scale)
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_i64(self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Amount, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(AmountVisitor)
+        } else {
+            Ok(Amount(i64::deserialize(deserializer)?))
+        }
+    }
+}
+
+// Deserializes an optional amount from the raw text of a CSV field rather than letting the csv
+// crate infer a float from it. Empty fields are None.
+pub fn deserialize_csv_amount<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Amount>, D::Error> {
+    return Ok(Option::<CSVAmount>::deserialize(deserializer)?.map(|a| a.0));
+}
+
+struct CSVAmount(Amount);
+
+impl<'de> Deserialize<'de> for CSVAmount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<CSVAmount, D::Error> {
+        Ok(CSVAmount(deserializer.deserialize_str(AmountVisitor)?))
+    }
+}
+
+struct AmountVisitor;
+
+impl<'de> Visitor<'de> for AmountVisitor {
+    type Value = Amount;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a decimal amount with at most {} decimal places", AMOUNT_DECIMAL_PLACES)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<Amount, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<Amount, E> {
+        v.checked_mul(AMOUNT_SCALE).map(Amount).ok_or_else(|| E::custom(AmountError::Overflow))
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<Amount, E> {
+        let v = i64::try_from(v).map_err(|_| E::custom(AmountError::Overflow))?;
+        self.visit_i64(v)
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<Amount, E> {
+        if !v.is_finite() {
+            return Err(E::custom(AmountError::Invalid(v.to_string())));
+        }
+        // Display gives the shortest representation that round-trips, so this recovers the
+        // decimal that was originally written.
+        self.visit_str(&v.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!("1.5".parse::<Amount>().unwrap(), Amount::from_scaled(15000));
+        assert_eq!("-0.0001".parse::<Amount>().unwrap(), Amount::from_scaled(-1));
+        assert_eq!(".25".parse::<Amount>().unwrap(), Amount::from_scaled(2500));
+        assert_eq!("2.00000".parse::<Amount>().unwrap(), Amount::from(2));
+        assert_eq!(Amount::from_scaled(-15).to_string(), "-0.0015");
+        assert_eq!(Amount::from(3).to_string(), "3.0000");
+
+        assert!(matches!("1.00001".parse::<Amount>(), Err(AmountError::TooManyDecimalPlaces(_))));
+        assert!(matches!("99999999999999999999".parse::<Amount>(), Err(AmountError::Overflow)));
+        assert!(matches!("1e5".parse::<Amount>(), Err(AmountError::Invalid(_))));
+        assert!(matches!(".".parse::<Amount>(), Err(AmountError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_checked_arithmetic() {
+        let mut total = Amount::ZERO;
+        for _ in 0..10_000 {
+            total = total.checked_add("0.1".parse().unwrap()).unwrap();
+        }
+        assert_eq!(total, Amount::from(1000));
+        assert_eq!(Amount::from_scaled(i64::MAX).checked_add(Amount::from_scaled(1)), Err(AmountError::Overflow));
+        assert_eq!(Amount::from_scaled(i64::MIN).checked_sub(Amount::from_scaled(1)), Err(AmountError::Overflow));
+    }
+
+    #[test]
+    fn test_serde() {
+        let amount: Amount = serde_json::from_str("1.2345").unwrap();
+        assert_eq!(amount, Amount::from_scaled(12345));
+        let amount: Amount = serde_json::from_str("\"1.2345\"").unwrap();
+        assert_eq!(amount, Amount::from_scaled(12345));
+        assert!(serde_json::from_str::<Amount>("1.23456").is_err());
+        assert_eq!(serde_json::to_string(&amount).unwrap(), "\"1.2345\"");
+
+        let buf = rmp_serde::to_vec_named(&amount).unwrap();
+        assert_eq!(rmp_serde::from_slice::<Amount>(&buf).unwrap(), amount);
+
+        let mut rdr = csv::ReaderBuilder::new().has_headers(false).from_reader("1.2345,\"7\"".as_bytes());
+        let row: (Amount, Amount) = rdr.deserialize().next().unwrap().unwrap();
+        assert_eq!(row, (Amount::from_scaled(12345), Amount::from(7)));
+
+        // past 15 significant digits a float would round the amount
+        let mut rdr = csv::Reader::from_reader("type,client,tx,amount\ndeposit,1,1,922337203685477.5807\ndispute,1,1,".as_bytes());
+        let rows: Vec<crate::model::CSVTransaction> = rdr.deserialize().map(|r| r.unwrap()).collect();
+        assert_eq!(rows[0].amount, Some(Amount::from_scaled(i64::MAX)));
+        assert_eq!(rows[1].amount, None);
+        let mut rdr = csv::Reader::from_reader("type,client,tx,amount\ndeposit,1,1,922337203685477.5808".as_bytes());
+        assert!(rdr.deserialize::<crate::model::CSVTransaction>().next().unwrap().is_err());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::model::{Amount, AmountError, Currency, Id, ID_LENGTH};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
@@ -5,10 +6,16 @@ use std::cmp::Ordering;
 #[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
 pub struct Client {
     pub id: u16,
-    pub available: f32,
-    pub held: f32,
-    pub total: f32,
+    // a client has one account per currency, each stored as a separate Client
+    #[serde(default)]
+    pub currency: Currency,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
     pub locked: bool,
+    // the fees charged to the account so far, they have already been taken out of available
+    #[serde(default)]
+    pub fees: Amount,
 
     pub creation_time: DateTime<Utc>,
     pub revision_number: u32,
@@ -18,21 +25,27 @@ pub struct Client {
 #[derive(Debug, Default, PartialOrd, Serialize, Deserialize)]
 pub struct CSVClient {
     pub client: u16,
-    pub available: f32,
-    pub held: f32,
-    pub total: f32,
+    pub available: Amount,
+    pub held: Amount,
+    pub total: Amount,
     pub locked: bool,
+    // added after the original columns, which consumers may read by position
+    #[serde(default)]
+    pub currency: Currency,
+    #[serde(default)]
+    pub fees: Amount,
 }
 
 impl Ord for CSVClient {
     fn cmp(&self, other: &Self) -> Ordering {
-        self.client.cmp(&other.client)
+        (self.client, self.currency).cmp(&(other.client, other.currency))
     }
 }
 
 impl PartialEq for CSVClient {
     fn eq(&self, other: &Self) -> bool {
-        (self.client, self.available, self.held, self.total, self.locked) == (other.client, other.available, other.held, other.total, other.locked)
+        (self.client, self.currency, self.available, self.held, self.total, self.locked, self.fees)
+            == (other.client, other.currency, other.available, other.held, other.total, other.locked, other.fees)
     }
 }
 
@@ -42,26 +55,30 @@ impl From<Client> for CSVClient {
     fn from(c: Client) -> Self {
         return Self {
             client: c.id,
+            currency: c.currency,
             available: c.available,
             held: c.held,
             total: c.total,
             locked: c.locked,
+            fees: c.fees,
         };
     }
 }
 
 #[derive(Clone, Default, Debug)]
 pub struct ClientPatch {
-    pub available: Option<f32>,
-    pub held: Option<f32>,
+    pub available: Option<Amount>,
+    pub held: Option<Amount>,
     pub locked: Option<bool>,
+    pub fees: Option<Amount>,
 }
 
 impl Client {
-    pub fn new(id: u16, available: Option<f32>) -> Self {
+    pub fn new(id: u16, currency: Currency, available: Option<Amount>) -> Self {
         let now = Utc::now();
         let mut ret = Client {
             id,
+            currency,
             locked: false,
             creation_time: now,
             revision_number: 1,
@@ -73,12 +90,16 @@ impl Client {
             ret.available = v;
         }
 
-        ret.total = ret.available + ret.held;
+        ret.total = ret.available;
 
         return ret;
     }
 
-    pub fn with_patch(mut self, p: ClientPatch) -> Self {
+    pub fn account_id(&self) -> Id {
+        account_id(self.id, self.currency)
+    }
+
+    pub fn with_patch(mut self, p: ClientPatch) -> Result<Self, AmountError> {
         self.revision_number += 1;
         self.revision_time = Utc::now();
         if let Some(available) = p.available {
@@ -90,9 +111,71 @@ impl Client {
         if let Some(locked) = p.locked {
             self.locked = locked;
         }
+        if let Some(fees) = p.fees {
+            self.fees = fees;
+        }
+
+        self.total = self.available.checked_add(self.held)?;
+
+        return Ok(self);
+    }
+}
+
+// A client's lock, kept once per client whatever the currencies of its accounts. Every write to
+// one of the client's accounts stores the lock's next revision, so the write fails with contention
+// if the lock changed since it was read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClientLock {
+    pub client: u16,
+    pub locked: bool,
+    pub revision_number: u32,
+    pub revision_time: DateTime<Utc>,
+}
+
+impl ClientLock {
+    // The lock of a client that doesn't have one stored yet, its next revision is the first.
+    pub fn new(client: u16, locked: bool) -> Self {
+        return ClientLock {
+            client,
+            locked,
+            revision_number: 0,
+            revision_time: Utc::now(),
+        };
+    }
+
+    pub fn next(&self, locked: bool) -> Self {
+        return ClientLock {
+            client: self.client,
+            locked,
+            revision_number: self.revision_number + 1,
+            revision_time: Utc::now(),
+        };
+    }
+}
+
+// The store id of a client's account in a currency. Accounts in the default currency keep the id
+// clients were stored under before they had one account per currency.
+pub fn account_id(client_id: u16, currency: Currency) -> Id {
+    let mut id = (client_id as u32).to_be_bytes().to_vec();
+    if currency != Currency::default() {
+        id.extend_from_slice(currency.as_bytes());
+    }
+    id.resize(ID_LENGTH, 0u8);
+    return Id::from(id);
+}
 
-        self.total = self.available + self.held;
+#[cfg(test)]
+mod test {
+    use super::*;
 
-        return self;
+    #[test]
+    fn test_csv_client_columns() {
+        let mut wtr = csv::Writer::from_writer(vec![]);
+        wtr.serialize(CSVClient::from(Client::new(1, Currency::USD, Some(Amount::from(2))))).unwrap();
+        let output = String::from_utf8(wtr.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            output,
+            "client,available,held,total,locked,currency,fees\n1,2.0000,0.0000,2.0000,false,USD,0.0000\n"
+        );
     }
 }
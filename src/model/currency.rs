@@ -0,0 +1,80 @@
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+// A three letter currency code such as "USD", always stored in upper case.
+//
+// Transactions that don't specify a currency are in the default currency, USD. Accounts in the
+// default currency are stored where clients were before accounts were split by currency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Currency([u8; 3]);
+
+impl Currency {
+    pub const USD: Currency = Currency(*b"USD");
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    pub fn as_str(&self) -> &str {
+        // currencies are only ever built from ascii letters
+        std::str::from_utf8(&self.0).unwrap_or_default()
+    }
+}
+
+impl Default for Currency {
+    fn default() -> Self {
+        Currency::USD
+    }
+}
+
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = s.trim().as_bytes();
+        if bytes.len() != 3 || !bytes.iter().all(|b| b.is_ascii_alphabetic()) {
+            return Err(format!("invalid currency {}", s));
+        }
+
+        let mut code = [0u8; 3];
+        for (c, b) in code.iter_mut().zip(bytes) {
+            *c = b.to_ascii_uppercase();
+        }
+        return Ok(Currency(code));
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Currency, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!("eur".parse::<Currency>().unwrap().to_string(), "EUR");
+        assert_eq!(" USD ".parse::<Currency>().unwrap(), Currency::USD);
+        assert_eq!(Currency::default(), Currency::USD);
+        for invalid in ["", "US", "USDT", "U5D", "€€"] {
+            assert!(invalid.parse::<Currency>().is_err(), "{}", invalid);
+        }
+    }
+}
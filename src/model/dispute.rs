@@ -1,36 +1,190 @@
 use crate::model;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisputeStatus {
+    Open,
+    Resolved,
+    ChargedBack,
+}
+
+impl DisputeStatus {
+    // Returns true if a dispute can go from this status to the other one. Disputes are opened once
+    // and then either resolved or charged back, after which they are final.
+    pub fn can_transition_to(&self, to: DisputeStatus) -> bool {
+        match (self, to) {
+            (DisputeStatus::Open, DisputeStatus::Resolved | DisputeStatus::ChargedBack) => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DisputeTransitionError {
+    pub from: DisputeStatus,
+    pub to: DisputeStatus,
+}
+
+impl std::error::Error for DisputeTransitionError {}
+
+impl fmt::Display for DisputeTransitionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a dispute cannot go from {:?} to {:?}", self.from, self.to)
+    }
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "StoredDispute")]
 pub struct Dispute {
     pub id: model::Id,
     pub referenced_tx: model::Transaction,
-    // This is the same thing as resolved and/or chargedback
-    pub is_deleted: bool,
+    // 1 for the first dispute of the referenced tx, incremented every time it is disputed again
+    pub dispute_number: u32,
+    // the funds held while the dispute is open, usually the amount of the referenced tx
+    pub held_amount: model::Amount,
+    pub status: DisputeStatus,
+    // the resolve or chargeback that closed the dispute, and when it did
+    pub resolved_by_tx: Option<model::Transaction>,
+    pub resolution_time: Option<DateTime<Utc>>,
 
     pub creation_time: DateTime<Utc>,
     pub revision_number: u32,
     pub revision_time: DateTime<Utc>,
 }
 
+// A dispute as it is stored. Disputes stored before they had a status only have is_deleted, which
+// was set once they were resolved or charged back, and always held the referenced tx amount.
+#[derive(Deserialize)]
+struct StoredDispute {
+    id: model::Id,
+    referenced_tx: model::Transaction,
+    #[serde(default)]
+    dispute_number: Option<u32>,
+    #[serde(default)]
+    held_amount: Option<model::Amount>,
+    #[serde(default)]
+    status: Option<DisputeStatus>,
+    #[serde(default)]
+    resolved_by_tx: Option<model::Transaction>,
+    #[serde(default)]
+    resolution_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    is_deleted: bool,
+
+    creation_time: DateTime<Utc>,
+    revision_number: u32,
+    revision_time: DateTime<Utc>,
+}
+
+impl From<StoredDispute> for Dispute {
+    fn from(d: StoredDispute) -> Self {
+        // how a legacy dispute was closed isn't recorded, a chargeback also locked the client so
+        // it's taken as resolved
+        let status = d.status.unwrap_or(match d.is_deleted {
+            true => DisputeStatus::Resolved,
+            false => DisputeStatus::Open,
+        });
+        return Dispute {
+            id: d.id,
+            dispute_number: d.dispute_number.unwrap_or(1),
+            held_amount: d.held_amount.unwrap_or(d.referenced_tx.amount),
+            referenced_tx: d.referenced_tx,
+            status,
+            resolved_by_tx: d.resolved_by_tx,
+            resolution_time: d.resolution_time,
+            creation_time: d.creation_time,
+            revision_number: d.revision_number,
+            revision_time: d.revision_time,
+        };
+    }
+}
+
 impl Dispute {
-    pub fn new(referenced_tx: model::Transaction) -> Self {
+    pub fn new(referenced_tx: model::Transaction, dispute_number: u32, held_amount: model::Amount) -> Self {
         let now = Utc::now();
         return Dispute {
             id: model::Id::generate(),
             referenced_tx,
-            is_deleted: false,
+            dispute_number,
+            held_amount,
+            status: DisputeStatus::Open,
+            resolved_by_tx: None,
+            resolution_time: None,
             creation_time: now,
             revision_number: 1,
             revision_time: now,
         };
     }
 
-    pub fn with_patch(mut self, is_deleted: bool) -> Self {
+    pub fn is_open(&self) -> bool {
+        self.status == DisputeStatus::Open
+    }
+
+    // Returns the next revision of the dispute, closed with the given status by the given
+    // transaction. This is the only way a dispute's status changes.
+    pub fn transition(mut self, to: DisputeStatus, by_tx: model::Transaction) -> Result<Self, DisputeTransitionError> {
+        if !self.status.can_transition_to(to) {
+            return Err(DisputeTransitionError { from: self.status, to });
+        }
+
+        let now = Utc::now();
         self.revision_number += 1;
-        self.revision_time = Utc::now();
-        self.is_deleted = is_deleted;
-        return self;
+        self.revision_time = now;
+        self.status = to;
+        self.resolved_by_tx = Some(by_tx);
+        self.resolution_time = Some(now);
+        return Ok(self);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Serialize)]
+    struct LegacyDispute {
+        id: model::Id,
+        referenced_tx: model::Transaction,
+        is_deleted: bool,
+        creation_time: DateTime<Utc>,
+        revision_number: u32,
+        revision_time: DateTime<Utc>,
+    }
+
+    #[test]
+    fn test_legacy_dispute() {
+        let referenced_tx = model::Transaction::from(model::CSVTransaction {
+            record_type: model::RecordType::Deposit,
+            client: 1,
+            tx: 1,
+            amount: Some("2.5".parse().unwrap()),
+            currency: None,
+            to_currency: None,
+        });
+        for (is_deleted, status) in [(false, DisputeStatus::Open), (true, DisputeStatus::Resolved)] {
+            let legacy = LegacyDispute {
+                id: model::Id::generate(),
+                referenced_tx: referenced_tx.clone(),
+                is_deleted,
+                creation_time: Utc::now(),
+                revision_number: 1,
+                revision_time: Utc::now(),
+            };
+            let dispute: Dispute = rmp_serde::from_slice(&rmp_serde::to_vec_named(&legacy).unwrap()).unwrap();
+            assert_eq!(dispute.status, status);
+            assert_eq!(dispute.dispute_number, 1);
+            assert_eq!(dispute.held_amount, referenced_tx.amount);
+            assert!(dispute.resolved_by_tx.is_none());
+        }
+
+        // disputes round trip as is
+        let dispute = Dispute::new(referenced_tx, 2, "1".parse().unwrap());
+        let stored: Dispute = rmp_serde::from_slice(&rmp_serde::to_vec_named(&dispute).unwrap()).unwrap();
+        assert_eq!(stored.dispute_number, 2);
+        assert_eq!(stored.held_amount, "1".parse().unwrap());
+        assert_eq!(stored.status, DisputeStatus::Open);
     }
 }
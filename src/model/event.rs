@@ -0,0 +1,97 @@
+use crate::model::{Amount, AmountError, Client, Currency, RecordType};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+// An entry of a client's append-only event log. Every change to a client's account is recorded as
+// the difference it made, so that folding a client's events rebuilds its account without having
+// to re-apply the engine's policies.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub client: u16,
+    // events are logged per account, i.e. per client and currency
+    #[serde(default)]
+    pub currency: Currency,
+    // the revision number of the client revision this event produced, events are ordered by it
+    pub sequence: u32,
+    // the applied record and the tx it referenced, None for the event opening the account
+    pub record_type: Option<RecordType>,
+    pub tx: Option<u32>,
+
+    pub available_change: Amount,
+    pub held_change: Amount,
+    // fees are already part of available_change, this only tracks the account's fee total
+    #[serde(default)]
+    pub fees_change: Amount,
+    pub locked: bool,
+
+    pub creation_time: DateTime<Utc>,
+}
+
+impl Event {
+    // The first event of a client, created along with its account.
+    pub fn opened(client: &Client) -> Self {
+        return Event {
+            client: client.id,
+            currency: client.currency,
+            sequence: client.revision_number,
+            record_type: None,
+            tx: None,
+            available_change: client.available,
+            held_change: client.held,
+            fees_change: client.fees,
+            locked: client.locked,
+            creation_time: client.creation_time,
+        };
+    }
+
+    // The event of an account rebuilt from its events. It doesn't change the account, it only
+    // records the revision the rebuilt account was stored as.
+    pub fn rebuilt(client: &Client) -> Self {
+        return Event {
+            client: client.id,
+            currency: client.currency,
+            sequence: client.revision_number,
+            record_type: None,
+            tx: None,
+            available_change: Amount::ZERO,
+            held_change: Amount::ZERO,
+            fees_change: Amount::ZERO,
+            locked: client.locked,
+            creation_time: client.revision_time,
+        };
+    }
+
+    // The event that took a client from one revision to the next.
+    pub fn new(before: &Client, after: &Client, record_type: RecordType, tx: u32) -> Result<Self, AmountError> {
+        return Ok(Event {
+            client: after.id,
+            currency: after.currency,
+            sequence: after.revision_number,
+            record_type: Some(record_type),
+            tx: Some(tx),
+            available_change: after.available.checked_sub(before.available)?,
+            held_change: after.held.checked_sub(before.held)?,
+            fees_change: after.fees.checked_sub(before.fees)?,
+            locked: after.locked,
+            creation_time: after.revision_time,
+        });
+    }
+
+    // Returns the client revision this event produced. The opening event applies to None.
+    pub fn apply(&self, client: Option<Client>) -> Result<Client, AmountError> {
+        let mut client = client.unwrap_or_else(|| Client {
+            id: self.client,
+            currency: self.currency,
+            creation_time: self.creation_time,
+            ..Default::default()
+        });
+        client.available = client.available.checked_add(self.available_change)?;
+        client.held = client.held.checked_add(self.held_change)?;
+        client.total = client.available.checked_add(client.held)?;
+        client.fees = client.fees.checked_add(self.fees_change)?;
+        client.locked = self.locked;
+        client.revision_number = self.sequence;
+        client.revision_time = self.creation_time;
+        return Ok(client);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::model::{parse_fixed_point, Amount, AmountError};
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::ser::{Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+// The number of decimal places every exchange rate is stored with.
+pub const FX_RATE_DECIMAL_PLACES: usize = 8;
+
+const FX_RATE_SCALE: i64 = 100_000_000;
+
+// A positive fixed-point exchange rate with eight decimal places, i.e. how much of the target
+// currency one unit of the source currency buys.
+//
+// Like amounts, human readable formats use decimal strings and also accept numbers, binary formats
+// store the scaled integer.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct FxRate(i64);
+
+impl FxRate {
+    // Returns the rate in units of 0.00000001.
+    pub fn scaled(&self) -> i64 {
+        self.0
+    }
+
+    // Converts an amount at this rate. The result is rounded toward zero, so a conversion never
+    // credits more than the rate gives.
+    pub fn convert(&self, amount: Amount) -> Result<Amount, AmountError> {
+        let converted = amount.scaled() as i128 * self.0 as i128 / FX_RATE_SCALE as i128;
+        return i64::try_from(converted).map(Amount::from_scaled).map_err(|_| AmountError::Overflow);
+    }
+}
+
+impl FromStr for FxRate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let scaled = parse_fixed_point(s, FX_RATE_DECIMAL_PLACES).map_err(|err| match err {
+            AmountError::Overflow => format!("fx rate {} is too large", s),
+            AmountError::TooManyDecimalPlaces(_) => format!("fx rate {} has more than {} decimal places", s, FX_RATE_DECIMAL_PLACES),
+            AmountError::Invalid(_) => format!("invalid fx rate {}", s),
+        })?;
+        if scaled <= 0 {
+            return Err(format!("fx rate {} must be positive", s));
+        }
+        return Ok(FxRate(scaled));
+    }
+}
+
+impl fmt::Display for FxRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let scale = FX_RATE_SCALE as u64;
+        let abs = self.0.unsigned_abs();
+        write!(f, "{}.{:08}", abs / scale, abs % scale)
+    }
+}
+
+impl Serialize for FxRate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_i64(self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for FxRate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<FxRate, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(FxRateVisitor)
+        } else {
+            Ok(FxRate(i64::deserialize(deserializer)?))
+        }
+    }
+}
+
+struct FxRateVisitor;
+
+impl<'de> Visitor<'de> for FxRateVisitor {
+    type Value = FxRate;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a positive decimal rate with at most {} decimal places", FX_RATE_DECIMAL_PLACES)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<FxRate, E> {
+        v.parse().map_err(E::custom)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<FxRate, E> {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<FxRate, E> {
+        self.visit_str(&v.to_string())
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<FxRate, E> {
+        // Display gives the shortest representation that round-trips
+        self.visit_str(&v.to_string())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_convert() {
+        let rate: FxRate = "0.92".parse().unwrap();
+        assert_eq!(rate.to_string(), "0.92000000");
+        assert_eq!(rate.convert(Amount::from(10)).unwrap(), Amount::from_scaled(92_000));
+        // 1.2345 * 1.23456789 = 1.524074060205, rounded down to 1.5240
+        let rate: FxRate = "1.23456789".parse().unwrap();
+        assert_eq!(rate.convert("1.2345".parse().unwrap()).unwrap(), Amount::from_scaled(15_240));
+
+        for invalid in ["", "0", "0.0", "-1", "1.123456789", "abc"] {
+            assert!(invalid.parse::<FxRate>().is_err(), "{}", invalid);
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use rand::RngCore;
 use serde::de::{Deserialize, Deserializer};
 use serde::ser::{Serialize, Serializer};
-use std::ops::Deref;
+use std::{fmt, ops::Deref, str::FromStr};
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq, PartialOrd)]
 pub struct Id(Vec<u8>);
@@ -61,6 +61,32 @@ impl Deref for Id {
     }
 }
 
+// Ids are displayed and parsed as lowercase hex, e.g. in API paths.
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in &self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Id {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != ID_LENGTH * 2 || !s.is_ascii() {
+            return Err(format!("invalid id {}", s));
+        }
+        let bytes = (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()
+            .map_err(|_| format!("invalid id {}", s))?;
+        Ok(Id(bytes))
+    }
+}
+
 impl<'de> Deserialize<'de> for Id {
     fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Id, D::Error> {
         Ok(Id(Vec::<u8>::deserialize(deserializer)?))
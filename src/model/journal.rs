@@ -0,0 +1,101 @@
+use crate::model::{Amount, Currency, RecordType};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// The general ledger accounts balance changes are posted to. Client balances are what the engine
+// owes its clients, settlement is where funds enter and leave the engine, and chargeback losses are
+// the funds that were charged back but couldn't be taken from the client. Exchange is where funds
+// converted from or to a currency leave or enter it, and fee income is what the engine charged in
+// fees. Every account is in a single currency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum LedgerAccount {
+    ClientAvailable(u16, Currency),
+    ClientHeld(u16, Currency),
+    Settlement(Currency),
+    ChargebackLoss(Currency),
+    Exchange(Currency),
+    FeeIncome(Currency),
+}
+
+impl LedgerAccount {
+    pub fn currency(&self) -> Currency {
+        match self {
+            LedgerAccount::ClientAvailable(_, currency)
+            | LedgerAccount::ClientHeld(_, currency)
+            | LedgerAccount::Settlement(currency)
+            | LedgerAccount::ChargebackLoss(currency)
+            | LedgerAccount::Exchange(currency)
+            | LedgerAccount::FeeIncome(currency) => *currency,
+        }
+    }
+}
+
+impl fmt::Display for LedgerAccount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LedgerAccount::ClientAvailable(id, currency) => write!(f, "client:{}:available:{}", id, currency),
+            LedgerAccount::ClientHeld(id, currency) => write!(f, "client:{}:held:{}", id, currency),
+            LedgerAccount::Settlement(currency) => write!(f, "settlement:{}", currency),
+            LedgerAccount::ChargebackLoss(currency) => write!(f, "chargeback_loss:{}", currency),
+            LedgerAccount::Exchange(currency) => write!(f, "exchange:{}", currency),
+            LedgerAccount::FeeIncome(currency) => write!(f, "fee_income:{}", currency),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PostingSide {
+    Debit,
+    Credit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Posting {
+    pub account: LedgerAccount,
+    pub side: PostingSide,
+    pub amount: Amount,
+}
+
+// The postings of a single operation. Postings are only added in debit/credit pairs of the same
+// amount, so an entry always balances.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub client: u16,
+    pub tx: u32,
+    pub record_type: RecordType,
+    pub postings: Vec<Posting>,
+
+    pub creation_time: DateTime<Utc>,
+}
+
+impl JournalEntry {
+    pub fn new(client: u16, tx: u32, record_type: RecordType, creation_time: DateTime<Utc>) -> Self {
+        return JournalEntry {
+            client,
+            tx,
+            record_type,
+            postings: vec![],
+            creation_time,
+        };
+    }
+
+    // Moves funds from the credited account to the debited one. Zero amounts aren't posted.
+    pub fn transfer(mut self, debit: LedgerAccount, credit: LedgerAccount, amount: Amount) -> Self {
+        if amount.is_zero() {
+            return self;
+        }
+        self.postings.push(Posting {
+            account: debit,
+            side: PostingSide::Debit,
+            amount,
+        });
+        self.postings.push(Posting {
+            account: credit,
+            side: PostingSide::Credit,
+            amount,
+        });
+        return self;
+    }
+}
@@ -1,3 +1,9 @@
+pub mod amount;
+pub use amount::*;
+pub mod currency;
+pub use currency::*;
+pub mod fx;
+pub use fx::*;
 pub mod transaction;
 pub use transaction::*;
 pub mod id;
@@ -6,3 +12,9 @@ pub mod client;
 pub use client::*;
 pub mod dispute;
 pub use dispute::*;
+pub mod adjustment;
+pub use adjustment::*;
+pub mod event;
+pub use event::*;
+pub mod journal;
+pub use journal::*;
@@ -1,5 +1,7 @@
+use crate::model::{deserialize_csv_amount, Amount, Currency, FxRate, Id, ID_LENGTH};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct CSVTransaction {
@@ -7,7 +9,14 @@ pub struct CSVTransaction {
     pub record_type: RecordType,
     pub client: u16,
     pub tx: u32,
-    pub amount: Option<f32>,
+    #[serde(default, deserialize_with = "deserialize_csv_amount")]
+    pub amount: Option<Amount>,
+    // the currency column is optional, rows without one are in the default currency
+    #[serde(default)]
+    pub currency: Option<Currency>,
+    // the currency conversions credit, only set for conversions
+    #[serde(default)]
+    pub to_currency: Option<Currency>,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -16,13 +25,56 @@ pub struct Transaction {
     pub record_type: RecordType,
     pub client: u16,
     pub tx: u32,
-    pub amount: f32,
+    pub amount: Amount,
+    // disputes, resolves and chargebacks apply in the currency of the tx they reference
+    #[serde(default)]
+    pub currency: Currency,
+    // conversions debit amount in currency and credit its converted value in to_currency, at the
+    // rate recorded in conversion once processed
+    #[serde(default)]
+    pub to_currency: Option<Currency>,
+    #[serde(default)]
+    pub conversion: Option<Conversion>,
+    // fees are linked to the tx they were charged for by its tx id and the record type charged for
+    #[serde(default)]
+    pub charged_for: Option<RecordType>,
 
     pub creation_time: DateTime<Utc>,
     pub revision_number: u32,
     pub revision_time: DateTime<Utc>,
 }
 
+// How a conversion was priced, recorded on the converted tx for audit.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Conversion {
+    // what was credited in the target currency
+    pub converted_amount: Amount,
+    pub rate: FxRate,
+    // when the rate took effect, None for rates that apply at any time
+    pub rate_time: Option<DateTime<Utc>>,
+}
+
+// A resubmission of a tx id that has already been processed. These are never applied, they are
+// only recorded so that replays and conflicting reuses of an id can be audited.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct DuplicateTransaction {
+    pub transaction: Transaction,
+    // true if the resubmission doesn't match the transaction that was originally processed
+    pub is_conflicting: bool,
+
+    pub creation_time: DateTime<Utc>,
+}
+
+impl DuplicateTransaction {
+    pub fn new(transaction: Transaction, is_conflicting: bool) -> Self {
+        return DuplicateTransaction {
+            transaction,
+            is_conflicting,
+            creation_time: Utc::now(),
+        };
+    }
+}
+
 #[derive(Clone, Default, Debug)]
 pub struct TransactionPatch {
     pub is_disputed: Option<bool>,
@@ -33,7 +85,7 @@ impl From<CSVTransaction> for Transaction {
         let now = Utc::now();
         let amount = match csv_tx.amount {
             Some(x) => x,
-            None => 0.0,
+            None => Amount::ZERO,
         };
 
         return Transaction {
@@ -41,6 +93,10 @@ impl From<CSVTransaction> for Transaction {
             client: csv_tx.client,
             tx: csv_tx.tx,
             amount,
+            currency: csv_tx.currency.unwrap_or_default(),
+            to_currency: csv_tx.to_currency,
+            conversion: None,
+            charged_for: None,
 
             creation_time: now,
             revision_number: 1,
@@ -49,6 +105,62 @@ impl From<CSVTransaction> for Transaction {
     }
 }
 
+impl Transaction {
+    // Returns true if other describes the same operation, ignoring timestamps and revisions.
+    pub fn is_same_operation(&self, other: &Transaction) -> bool {
+        (self.record_type, self.client, self.tx, self.amount, self.currency, self.to_currency)
+            == (other.record_type, other.client, other.tx, other.amount, other.currency, other.to_currency)
+    }
+
+    // The id the tx is stored under.
+    pub fn id(&self) -> Id {
+        match self.charged_for {
+            Some(charged_for) => fee_transaction_id(self.tx, charged_for),
+            None if self.record_type.is_adjustment() => adjustment_transaction_id(self.tx),
+            None => Id::from(self.tx),
+        }
+    }
+
+    // The fee charged for this tx, in its currency. It shares the tx's creation time, and is listed
+    // right after it since its id sorts after the tx's.
+    pub fn fee(&self, amount: Amount) -> Transaction {
+        let creation_time = self.creation_time;
+        return Transaction {
+            record_type: RecordType::Fee,
+            client: self.client,
+            tx: self.tx,
+            amount,
+            currency: self.currency,
+            to_currency: None,
+            conversion: None,
+            charged_for: Some(self.record_type),
+
+            creation_time,
+            revision_number: 1,
+            revision_time: creation_time,
+        };
+    }
+}
+
+// The store id of the fee charged for a tx. Fees share the tx id of the tx they were charged for,
+// so the record type charged for keeps e.g. a deposit's fee apart from the fee of its chargeback.
+pub fn fee_transaction_id(tx: u32, charged_for: RecordType) -> Id {
+    let mut id = tx.to_be_bytes().to_vec();
+    id.extend_from_slice(b"fee:");
+    id.extend_from_slice(charged_for.to_string().as_bytes());
+    id.resize(ID_LENGTH, 0u8);
+    return Id::from(id);
+}
+
+// The store id of an adjustment. Adjustment tx ids are picked by administrators, so they are kept
+// apart from the tx ids of regular transactions, which are picked by partners.
+pub fn adjustment_transaction_id(tx: u32) -> Id {
+    let mut id = tx.to_be_bytes().to_vec();
+    id.extend_from_slice(b"adjustment");
+    id.resize(ID_LENGTH, 0u8);
+    return Id::from(id);
+}
+
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum RecordType {
@@ -57,4 +169,59 @@ pub enum RecordType {
     Dispute,
     Resolve,
     Chargeback,
+    // Moves funds between two accounts of a client in different currencies.
+    Conversion,
+    // Charged by the engine along with the tx it is linked to, it can't be submitted.
+    Fee,
+    // Administrative adjustments, these can only be applied through the admin API.
+    Lock,
+    Unlock,
+    Credit,
+    Debit,
+}
+
+impl RecordType {
+    pub fn is_adjustment(&self) -> bool {
+        matches!(self, RecordType::Lock | RecordType::Unlock | RecordType::Credit | RecordType::Debit)
+    }
+}
+
+impl FromStr for RecordType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deposit" => Ok(RecordType::Deposit),
+            "withdrawal" => Ok(RecordType::Withdrawal),
+            "dispute" => Ok(RecordType::Dispute),
+            "resolve" => Ok(RecordType::Resolve),
+            "chargeback" => Ok(RecordType::Chargeback),
+            "conversion" => Ok(RecordType::Conversion),
+            "fee" => Ok(RecordType::Fee),
+            "lock" => Ok(RecordType::Lock),
+            "unlock" => Ok(RecordType::Unlock),
+            "credit" => Ok(RecordType::Credit),
+            "debit" => Ok(RecordType::Debit),
+            _ => Err(format!("unknown record type {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for RecordType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            RecordType::Deposit => "deposit",
+            RecordType::Withdrawal => "withdrawal",
+            RecordType::Dispute => "dispute",
+            RecordType::Resolve => "resolve",
+            RecordType::Chargeback => "chargeback",
+            RecordType::Conversion => "conversion",
+            RecordType::Fee => "fee",
+            RecordType::Lock => "lock",
+            RecordType::Unlock => "unlock",
+            RecordType::Credit => "credit",
+            RecordType::Debit => "debit",
+        };
+        write!(f, "{}", s)
+    }
 }
@@ -5,21 +5,32 @@ use crate::{
     store::{Error, Result, Store},
 };
 use chrono::{DateTime, Utc};
-use keyvaluestore::{AtomicWriteOperation, Backend};
+use keyvaluestore::{AtomicWriteOperation, Backend, BatchOperation};
 
 pub const CLIENTS_SET_KEY: &str = "clients";
 pub const CLIENT_KEY: &str = "client";
 pub const CLIENT_REVISION_KEY: &str = "client_revision";
+// Holds the ids of a client's accounts, one per currency, scored by creation time.
+pub const CLIENT_ACCOUNTS_SET_KEY: &str = "client_accounts";
+pub const CLIENT_LOCK_KEY: &str = "client_lock";
+pub const CLIENT_LOCK_REVISION_KEY: &str = "client_lock_revision";
 
 impl<B: Backend + Sync> Store<B> {
     pub async fn add_client(&self, client: &Client) -> Result<()> {
         let serialized = Self::serialize(&client)?;
-        let id = model::Id::from(client.id as u32);
+        let id = client.account_id();
+        let client_id = model::Id::from(client.id as u32);
 
         let mut tx = AtomicWriteOperation::new();
         tx.z_add(CLIENTS_SET_KEY, id.as_ref(), Self::time_microsecond_score(&client.revision_time));
+        tx.z_add(
+            store_key!(CLIENT_ACCOUNTS_SET_KEY, ":", client_id),
+            id.as_ref(),
+            Self::time_microsecond_score(&client.creation_time),
+        );
         tx.set_nx(store_key!(CLIENT_KEY, ":", id), &serialized);
         tx.set_nx(store_key!(CLIENT_REVISION_KEY, ":", id, ":", client.revision_number), &serialized);
+        Self::add_event(&mut tx, &model::Event::opened(client))?;
         match self.backend.exec_atomic_write(tx).await? {
             true => Ok(()),
             false => Err(Error::Contention),
@@ -28,7 +39,7 @@ impl<B: Backend + Sync> Store<B> {
 
     pub async fn add_client_revision(&self, client: &Client) -> Result<()> {
         let serialized = Self::serialize(&client)?;
-        let id = model::Id::from(client.id as u32);
+        let id = client.account_id();
 
         let mut tx = AtomicWriteOperation::new();
         tx.z_add(CLIENTS_SET_KEY, id.as_ref(), Self::time_microsecond_score(&client.revision_time));
@@ -40,8 +51,48 @@ impl<B: Backend + Sync> Store<B> {
         }
     }
 
-    pub async fn get_client_by_id(&self, client_id: u16) -> Result<Option<Client>> {
+    // Adds the next revision of a client's lock to the given write, which fails if another write
+    // stored it first.
+    pub(crate) fn add_client_lock(tx: &mut AtomicWriteOperation, lock: &ClientLock) -> Result<()> {
+        let serialized = Self::serialize(lock)?;
+        let client_id = model::Id::from(lock.client as u32);
+        tx.set(store_key!(CLIENT_LOCK_KEY, ":", client_id), &serialized);
+        tx.set_nx(store_key!(CLIENT_LOCK_REVISION_KEY, ":", client_id, ":", lock.revision_number), &serialized);
+        return Ok(());
+    }
+
+    pub async fn get_client_lock(&self, client_id: u16) -> Result<Option<ClientLock>> {
         let id = model::Id::from(client_id as u32);
+        if let Some(v) = self.backend.get(store_key!(CLIENT_LOCK_KEY, ":", id)).await? {
+            let ret: ClientLock = Self::deserialize(v.as_ref())?;
+            return Ok(Some(ret));
+        } else {
+            return Ok(None);
+        }
+    }
+
+    // Overwrites a client's current state with the one replayed from its events, as a new revision
+    // along with the event recording it. The client's revision number must follow its latest one,
+    // otherwise this fails with contention.
+    pub async fn restore_client(&self, client: &Client, lock: &ClientLock, event: &model::Event) -> Result<()> {
+        let serialized = Self::serialize(&client)?;
+        let id = client.account_id();
+
+        let mut tx = AtomicWriteOperation::new();
+        Self::add_client_lock(&mut tx, lock)?;
+        tx.z_add(CLIENTS_SET_KEY, id.as_ref(), Self::time_microsecond_score(&client.revision_time));
+        tx.set(store_key!(CLIENT_KEY, ":", id), &serialized);
+        tx.set_nx(store_key!(CLIENT_REVISION_KEY, ":", id, ":", client.revision_number), &serialized);
+        Self::add_event(&mut tx, event)?;
+        match self.backend.exec_atomic_write(tx).await? {
+            true => Ok(()),
+            false => Err(Error::Contention),
+        }
+    }
+
+    // Gets a client's account in a currency.
+    pub async fn get_client_by_id(&self, client_id: u16, currency: model::Currency) -> Result<Option<Client>> {
+        let id = model::account_id(client_id, currency);
         if let Some(v) = self.backend.get(store_key!(CLIENT_KEY, ":", id)).await? {
             let ret: Client = Self::deserialize(v.as_ref())?;
             return Ok(Some(ret));
@@ -50,6 +101,63 @@ impl<B: Backend + Sync> Store<B> {
         }
     }
 
+    pub async fn get_client_revision(&self, client_id: u16, currency: model::Currency, revision_number: u32) -> Result<Option<Client>> {
+        let id = model::account_id(client_id, currency);
+        if let Some(v) = self.backend.get(store_key!(CLIENT_REVISION_KEY, ":", id, ":", revision_number)).await? {
+            let ret: Client = Self::deserialize(v.as_ref())?;
+            return Ok(Some(ret));
+        } else {
+            return Ok(None);
+        }
+    }
+
+    // Gets the revisions of a client's account within an inclusive range of revision numbers, in
+    // order. Revisions that don't exist are skipped.
+    pub async fn get_client_revisions(&self, client_id: u16, currency: model::Currency, min_revision: u32, max_revision: u32) -> Result<Vec<Client>> {
+        let id = model::account_id(client_id, currency);
+
+        let mut batch = BatchOperation::new();
+        let gets: Vec<_> = (min_revision..=max_revision)
+            .map(|n| batch.get(store_key!(CLIENT_REVISION_KEY, ":", id, ":", n)))
+            .collect();
+        self.backend.exec_batch(batch).await?;
+
+        let mut ret = Vec::with_capacity(gets.len());
+        for get in gets {
+            if let Some(v) = get.value() {
+                ret.push(Self::deserialize(v.as_ref())?);
+            }
+        }
+        return Ok(ret);
+    }
+
+    // Gets the given revisions of any client accounts in a single batch, None for those that don't
+    // exist.
+    pub async fn get_client_revisions_by_number(&self, revisions: &[(u16, model::Currency, u32)]) -> Result<Vec<Option<Client>>> {
+        let mut batch = BatchOperation::new();
+        let gets: Vec<_> = revisions
+            .iter()
+            .map(|(client_id, currency, n)| batch.get(store_key!(CLIENT_REVISION_KEY, ":", model::account_id(*client_id, *currency), ":", *n)))
+            .collect();
+        self.backend.exec_batch(batch).await?;
+
+        let mut ret = Vec::with_capacity(gets.len());
+        for get in gets {
+            ret.push(match get.value() {
+                Some(v) => Some(Self::deserialize(v.as_ref())?),
+                None => None,
+            });
+        }
+        return Ok(ret);
+    }
+
+    // Gets every account of a client, in the order they were opened.
+    pub async fn get_client_accounts(&self, client_id: u16) -> Result<Vec<Client>> {
+        let id = model::Id::from(client_id as u32);
+        self.get_by_score(store_key!(CLIENT_ACCOUNTS_SET_KEY, ":", id), f64::NEG_INFINITY, f64::INFINITY, 0, CLIENT_KEY)
+            .await
+    }
+
     // Gets clients within an inclusive time range. If limit is non-zero, the returned events will
     // be limited to that number. If limit is negative, the returned clients will be the last clients
     // in the range.
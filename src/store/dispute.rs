@@ -1,38 +1,74 @@
 use crate::store_key;
 use crate::{
     model,
-    store::{client::*, Error, Result, Store},
+    store::{client::*, transaction::TRANSACTIONS_SET_KEY, Error, Result, Store},
 };
-use keyvaluestore::{AtomicWriteOperation, Backend};
+use keyvaluestore::{AtomicWriteOperation, Backend, BatchOperation};
 
 pub const DISPUTE_KEY: &str = "dispute";
 pub const DISPUTE_REVISION_KEY: &str = "dispute_revision";
+// Holds the latest dispute of a tx.
 pub const REFERENCE_TX_DISPUTE_KEY: &str = "reference_tx_dispute";
+// Holds all of a tx's disputes, scored by dispute number.
+pub const REFERENCE_TX_DISPUTES_SET_KEY: &str = "reference_tx_disputes";
+// Only holds a client's open disputes.
 pub const CLIENT_DISPUTES_SET_KEY: &str = "client_disputes";
+// Holds all of a client's disputes, open or closed.
+pub const CLIENT_DISPUTES_HISTORY_SET_KEY: &str = "client_disputes_history";
 
 impl<B: Backend + Sync> Store<B> {
-    pub async fn process_dispute(&self, client: &model::Client, dispute: &model::Dispute) -> Result<()> {
+    pub async fn process_dispute(
+        &self,
+        client: &model::Client,
+        lock: &model::ClientLock,
+        dispute: &model::Dispute,
+        event: &model::Event,
+        entry: &model::JournalEntry,
+    ) -> Result<()> {
         let serialized = Self::serialize(&dispute)?;
         let reference_tx_id = model::Id::from(dispute.referenced_tx.tx);
 
         let serialized_client = Self::serialize(client)?;
         let client_id = model::Id::from(client.id as u32);
+        let account_id = client.account_id();
 
         let mut tx = AtomicWriteOperation::new();
+        Self::add_client_lock(&mut tx, lock)?;
         // update client with new available/held values
-        tx.z_add(CLIENTS_SET_KEY, client_id.as_ref(), Self::time_microsecond_score(&client.revision_time));
-        tx.set(store_key!(CLIENT_KEY, ":", client_id), &serialized_client);
-        tx.set_nx(store_key!(CLIENT_REVISION_KEY, ":", client_id, ":", client.revision_number), &serialized_client);
+        tx.z_add(CLIENTS_SET_KEY, account_id.as_ref(), Self::time_microsecond_score(&client.revision_time));
+        tx.set(store_key!(CLIENT_KEY, ":", account_id), &serialized_client);
+        tx.set_nx(
+            store_key!(CLIENT_REVISION_KEY, ":", account_id, ":", client.revision_number),
+            &serialized_client,
+        );
+        Self::add_event(&mut tx, event)?;
+        Self::add_journal_entry(&mut tx, entry)?;
 
         // add a dispute
         tx.set_nx(store_key!(DISPUTE_KEY, ":", dispute.id), &serialized);
         tx.set_nx(store_key!(DISPUTE_REVISION_KEY, ":", dispute.id, ":", dispute.revision_number), &serialized);
-        tx.set_nx(store_key!(REFERENCE_TX_DISPUTE_KEY, ":", reference_tx_id), &serialized);
+        // a tx can be disputed again once its previous dispute is closed, the numbered key makes sure
+        // two concurrent disputes of the same tx can't both succeed
+        tx.set(store_key!(REFERENCE_TX_DISPUTE_KEY, ":", reference_tx_id), &serialized);
+        tx.set_nx(
+            store_key!(REFERENCE_TX_DISPUTE_KEY, ":", reference_tx_id, ":", dispute.dispute_number),
+            dispute.id.as_ref(),
+        );
+        tx.z_add(
+            store_key!(REFERENCE_TX_DISPUTES_SET_KEY, ":", reference_tx_id),
+            dispute.id.as_ref(),
+            dispute.dispute_number as f64,
+        );
         tx.z_add(
             store_key!(CLIENT_DISPUTES_SET_KEY, ":", client_id),
             dispute.id.as_ref(),
             Self::time_microsecond_score(&dispute.revision_time),
         );
+        tx.z_add(
+            store_key!(CLIENT_DISPUTES_HISTORY_SET_KEY, ":", client_id),
+            dispute.id.as_ref(),
+            Self::time_microsecond_score(&dispute.creation_time),
+        );
 
         match self.backend.exec_atomic_write(tx).await? {
             true => Ok(()),
@@ -40,25 +76,44 @@ impl<B: Backend + Sync> Store<B> {
         }
     }
 
-    // This removes the dispute from the tx and updates the client accordingly
-    pub async fn remove_dispute(&self, client: &model::Client, dispute: &model::Dispute) -> Result<()> {
+    // This stores the revision closing the dispute, removes it from the client's open disputes and
+    // updates the client accordingly, charging the chargeback fee if there is one
+    pub async fn remove_dispute(
+        &self,
+        client: &model::Client,
+        lock: &model::ClientLock,
+        dispute: &model::Dispute,
+        fee: Option<&model::Transaction>,
+        event: &model::Event,
+        entry: &model::JournalEntry,
+    ) -> Result<()> {
         let serialized = Self::serialize(&dispute)?;
         let reference_tx_id = model::Id::from(dispute.referenced_tx.tx);
 
         let serialized_client = Self::serialize(client)?;
         let client_id = model::Id::from(client.id as u32);
+        let account_id = client.account_id();
 
         let mut tx = AtomicWriteOperation::new();
+        Self::add_client_lock(&mut tx, lock)?;
         // update client with new available/held values
-        tx.z_add(CLIENTS_SET_KEY, client_id.as_ref(), Self::time_microsecond_score(&client.revision_time));
-        tx.set(store_key!(CLIENT_KEY, ":", client_id), &serialized_client);
-        tx.set_nx(store_key!(CLIENT_REVISION_KEY, ":", client_id, ":", client.revision_number), &serialized_client);
+        tx.z_add(CLIENTS_SET_KEY, account_id.as_ref(), Self::time_microsecond_score(&client.revision_time));
+        tx.set(store_key!(CLIENT_KEY, ":", account_id), &serialized_client);
+        tx.set_nx(
+            store_key!(CLIENT_REVISION_KEY, ":", account_id, ":", client.revision_number),
+            &serialized_client,
+        );
+        Self::add_event(&mut tx, event)?;
+        Self::add_journal_entry(&mut tx, entry)?;
 
         // remove dispute
         tx.set(store_key!(DISPUTE_KEY, ":", dispute.id), &serialized);
         tx.set_nx(store_key!(DISPUTE_REVISION_KEY, ":", dispute.id, ":", dispute.revision_number), &serialized);
         tx.set(store_key!(REFERENCE_TX_DISPUTE_KEY, ":", reference_tx_id), &serialized);
         tx.z_rem(store_key!(CLIENT_DISPUTES_SET_KEY, ":", client_id), dispute.id.as_ref());
+        if let Some(fee) = fee {
+            Self::add_fee_transaction(&mut tx, fee)?;
+        }
 
         match self.backend.exec_atomic_write(tx).await? {
             true => Ok(()),
@@ -66,6 +121,86 @@ impl<B: Backend + Sync> Store<B> {
         }
     }
 
+    pub async fn get_dispute_by_id(&self, id: &model::Id) -> Result<Option<model::Dispute>> {
+        if let Some(v) = self.backend.get(store_key!(DISPUTE_KEY, ":", id)).await? {
+            let ret: model::Dispute = Self::deserialize(v.as_ref())?;
+            return Ok(Some(ret));
+        } else {
+            return Ok(None);
+        }
+    }
+
+    // Gets every revision of a dispute up to the given revision number, oldest first.
+    pub async fn get_dispute_revisions(&self, id: &model::Id, max_revision: u32) -> Result<Vec<model::Dispute>> {
+        let mut batch = BatchOperation::new();
+        let gets: Vec<_> = (1..=max_revision)
+            .map(|n| batch.get(store_key!(DISPUTE_REVISION_KEY, ":", id, ":", n)))
+            .collect();
+        self.backend.exec_batch(batch).await?;
+
+        let mut ret = Vec::with_capacity(gets.len());
+        for get in gets {
+            if let Some(v) = get.value() {
+                ret.push(Self::deserialize(v.as_ref())?);
+            }
+        }
+        return Ok(ret);
+    }
+
+    // Gets a client's disputes, oldest first. Closed disputes are only included if include_closed is set.
+    pub async fn get_client_disputes(&self, client_id: u16, include_closed: bool) -> Result<Vec<model::Dispute>> {
+        let id = model::Id::from(client_id as u32);
+        let open = self
+            .get_by_score(store_key!(CLIENT_DISPUTES_SET_KEY, ":", id), f64::NEG_INFINITY, f64::INFINITY, 0, DISPUTE_KEY)
+            .await?;
+        if !include_closed {
+            return Ok(open);
+        }
+
+        // disputes stored before the history set existed are only in the open set while open, once
+        // closed they can only be found through the tx they reference
+        let history = self
+            .get_by_score(
+                store_key!(CLIENT_DISPUTES_HISTORY_SET_KEY, ":", id),
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                0,
+                DISPUTE_KEY,
+            )
+            .await?;
+        let latest_by_tx = self
+            .get_by_score(
+                store_key!(TRANSACTIONS_SET_KEY, ":", CLIENT_KEY, ":", id),
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                0,
+                REFERENCE_TX_DISPUTE_KEY,
+            )
+            .await?;
+        let mut ret = merge_disputes(history, open.into_iter().chain(latest_by_tx));
+        ret.sort_by_key(|d| d.creation_time);
+        return Ok(ret);
+    }
+
+    // Gets every dispute of a tx, oldest first. A dispute stored before the set of a tx's disputes
+    // existed is only kept as the tx's latest dispute.
+    pub async fn get_disputes_by_reference_tx_id(&self, tx_id: u32) -> Result<Vec<model::Dispute>> {
+        let id = model::Id::from(tx_id as u32);
+        let disputes = self
+            .get_by_score(
+                store_key!(REFERENCE_TX_DISPUTES_SET_KEY, ":", id),
+                f64::NEG_INFINITY,
+                f64::INFINITY,
+                0,
+                DISPUTE_KEY,
+            )
+            .await?;
+        let latest = self.get_dispute_by_reference_tx_id(tx_id).await?;
+        let mut ret = merge_disputes(disputes, latest);
+        ret.sort_by_key(|d| d.dispute_number);
+        return Ok(ret);
+    }
+
     pub async fn get_dispute_by_reference_tx_id(&self, tx_id: u32) -> Result<Option<model::Dispute>> {
         let id = model::Id::from(tx_id as u32);
         if let Some(v) = self.backend.get(store_key!(REFERENCE_TX_DISPUTE_KEY, ":", id)).await? {
@@ -76,3 +211,76 @@ impl<B: Backend + Sync> Store<B> {
         }
     }
 }
+
+// Adds the disputes that aren't in the list yet, the same dispute may be read through several
+// indexes.
+fn merge_disputes(mut disputes: Vec<model::Dispute>, others: impl IntoIterator<Item = model::Dispute>) -> Vec<model::Dispute> {
+    for dispute in others {
+        if !disputes.iter().any(|d| d.id == dispute.id) {
+            disputes.push(dispute);
+        }
+    }
+    return disputes;
+}
+
+#[cfg(test)]
+impl<B: Backend + Sync> Store<B> {
+    // Stores a client with deposits and disputes of them the way they were stored before accounts
+    // had a currency and disputes a status, without any of the indexes, events or journal entries
+    // added since. Each dispute is given by the tx id it references and whether it is closed.
+    pub async fn add_legacy_client(&self, client: &model::Client, deposits: &[model::Transaction], disputes: &[(u32, bool)]) -> Result<()> {
+        use crate::store::transaction::TRANSACTION_KEY;
+        use chrono::{DateTime, Utc};
+
+        #[derive(serde::Serialize)]
+        struct LegacyDispute {
+            id: model::Id,
+            referenced_tx: model::Transaction,
+            is_deleted: bool,
+            creation_time: DateTime<Utc>,
+            revision_number: u32,
+            revision_time: DateTime<Utc>,
+        }
+
+        let client_id = model::Id::from(client.id as u32);
+        let account_id = client.account_id();
+        let serialized_client = Self::serialize(client)?;
+        let mut tx = AtomicWriteOperation::new();
+        tx.z_add(CLIENTS_SET_KEY, account_id.as_ref(), Self::time_microsecond_score(&client.revision_time));
+        tx.set(store_key!(CLIENT_KEY, ":", account_id), &serialized_client);
+        tx.set(
+            store_key!(CLIENT_REVISION_KEY, ":", account_id, ":", client.revision_number),
+            &serialized_client,
+        );
+        for deposit in deposits {
+            let tx_id = model::Id::from(deposit.tx);
+            let score = Self::time_microsecond_score(&deposit.creation_time);
+            tx.z_add(TRANSACTIONS_SET_KEY, tx_id.as_ref(), score);
+            tx.z_add(store_key!(TRANSACTIONS_SET_KEY, ":", CLIENT_KEY, ":", client_id), tx_id.as_ref(), score);
+            tx.set(store_key!(TRANSACTION_KEY, ":", tx_id), Self::serialize(deposit)?);
+        }
+        for (tx_id, is_closed) in disputes {
+            let referenced_tx = *deposits.iter().find(|d| d.tx == *tx_id).expect("disputed deposit");
+            let dispute = LegacyDispute {
+                id: model::Id::generate(),
+                referenced_tx,
+                is_deleted: *is_closed,
+                creation_time: referenced_tx.creation_time,
+                revision_number: if *is_closed { 2 } else { 1 },
+                revision_time: referenced_tx.creation_time,
+            };
+            let serialized = Self::serialize(&dispute)?;
+            tx.set(store_key!(DISPUTE_KEY, ":", dispute.id), &serialized);
+            tx.set(store_key!(REFERENCE_TX_DISPUTE_KEY, ":", model::Id::from(*tx_id)), &serialized);
+            if !is_closed {
+                tx.z_add(
+                    store_key!(CLIENT_DISPUTES_SET_KEY, ":", client_id),
+                    dispute.id.as_ref(),
+                    Self::time_microsecond_score(&dispute.revision_time),
+                );
+            }
+        }
+        self.backend.exec_atomic_write(tx).await?;
+        return Ok(());
+    }
+}
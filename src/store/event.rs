@@ -0,0 +1,28 @@
+use crate::store_key;
+use crate::{
+    model,
+    store::{Result, Store},
+};
+use keyvaluestore::{AtomicWriteOperation, Backend};
+
+// Holds the events of a client's account in one currency, scored by sequence. Events are only ever added, in the same atomic
+// write as the client revision they produce.
+pub const CLIENT_EVENTS_SET_KEY: &str = "client_events";
+
+impl<B: Backend + Sync> Store<B> {
+    // Adds an event to the given write. The write also has to set_nx the client revision the event
+    // produced, which is what keeps a sequence number from being used twice.
+    pub(crate) fn add_event(tx: &mut AtomicWriteOperation, event: &model::Event) -> Result<()> {
+        let serialized = Self::serialize(event)?;
+        let account_id = model::account_id(event.client, event.currency);
+        tx.z_add(store_key!(CLIENT_EVENTS_SET_KEY, ":", account_id), serialized, event.sequence as f64);
+        return Ok(());
+    }
+
+    // Gets every event of a client's account in a currency, in order.
+    pub async fn get_client_events(&self, client_id: u16, currency: model::Currency) -> Result<Vec<model::Event>> {
+        let id = model::account_id(client_id, currency);
+        self.get_by_score(store_key!(CLIENT_EVENTS_SET_KEY, ":", id), f64::NEG_INFINITY, f64::INFINITY, 0, "")
+            .await
+    }
+}
@@ -0,0 +1,29 @@
+use crate::{
+    model,
+    store::{Result, Store},
+};
+use chrono::{DateTime, Utc};
+use keyvaluestore::{AtomicWriteOperation, Backend};
+
+// Holds every journal entry, scored by creation time. Entries are only ever added, in the same
+// atomic write as the balance change they post.
+pub const JOURNAL_ENTRIES_SET_KEY: &str = "journal_entries";
+
+impl<B: Backend + Sync> Store<B> {
+    // Adds a journal entry to the given write. Entries without postings, e.g. for locks, are
+    // skipped.
+    pub(crate) fn add_journal_entry(tx: &mut AtomicWriteOperation, entry: &model::JournalEntry) -> Result<()> {
+        if entry.postings.is_empty() {
+            return Ok(());
+        }
+        let serialized = Self::serialize(entry)?;
+        tx.z_add(JOURNAL_ENTRIES_SET_KEY, serialized, Self::time_microsecond_score(&entry.creation_time));
+        return Ok(());
+    }
+
+    // Gets journal entries within an inclusive time range, oldest first. If limit is non-zero, only
+    // the first limit entries are returned.
+    pub async fn get_journal_entries_by_time_range(&self, min: DateTime<Utc>, max: DateTime<Utc>, limit: i32) -> Result<Vec<model::JournalEntry>> {
+        self.get_by_time_range(JOURNAL_ENTRIES_SET_KEY, min, max, limit, "").await
+    }
+}
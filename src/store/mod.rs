@@ -21,6 +21,10 @@ pub mod transaction;
 pub use transaction::*;
 pub mod dispute;
 pub use dispute::*;
+pub mod event;
+pub use event::*;
+pub mod journal;
+pub use journal::*;
 
 #[derive(Clone)]
 pub struct Store<B> {
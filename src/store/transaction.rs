@@ -11,23 +11,170 @@ use keyvaluestore::{AtomicWriteOperation, Backend};
 pub const TRANSACTIONS_SET_KEY: &str = "transactions";
 pub const TRANSACTION_KEY: &str = "transaction";
 pub const TRANSACTION_REVISION_KEY: &str = "transaction_revision";
+pub const TRANSACTION_DUPLICATES_SET_KEY: &str = "transaction_duplicates";
+pub const ADJUSTMENT_KEY: &str = "adjustment";
+// Holds the client that first used a tx id when CSV records are processed by several workers.
+pub const TRANSACTION_CLAIM_KEY: &str = "transaction_claim";
+// Holds the fees charged for a tx, scored by creation time.
+pub const TRANSACTION_FEES_SET_KEY: &str = "transaction_fees";
 
 impl<B: Backend + Sync> Store<B> {
-    pub async fn process_transaction(&self, client: &Client, transaction: &Transaction) -> Result<()> {
+    pub async fn process_transaction(
+        &self,
+        client: &Client,
+        lock: &ClientLock,
+        transaction: &Transaction,
+        fee: Option<&Transaction>,
+        event: &model::Event,
+        entry: &model::JournalEntry,
+    ) -> Result<()> {
         let serialized_client = Self::serialize(client)?;
         let client_id = model::Id::from(client.id as u32);
+        let account_id = client.account_id();
 
         let serialized_transaction = Self::serialize(transaction)?;
         let tx_id = model::Id::from(transaction.tx);
 
         let mut tx = AtomicWriteOperation::new();
+        Self::add_client_lock(&mut tx, lock)?;
         // update the client with new values from transaction
-        tx.z_add(CLIENTS_SET_KEY, client_id.as_ref(), Self::time_microsecond_score(&client.revision_time));
-        tx.set(store_key!(CLIENT_KEY, ":", client_id), &serialized_client);
-        tx.set_nx(store_key!(CLIENT_REVISION_KEY, ":", client_id, ":", client.revision_number), &serialized_client);
+        tx.z_add(CLIENTS_SET_KEY, account_id.as_ref(), Self::time_microsecond_score(&client.revision_time));
+        tx.set(store_key!(CLIENT_KEY, ":", account_id), &serialized_client);
+        tx.set_nx(
+            store_key!(CLIENT_REVISION_KEY, ":", account_id, ":", client.revision_number),
+            &serialized_client,
+        );
+        Self::add_event(&mut tx, event)?;
+        Self::add_journal_entry(&mut tx, entry)?;
 
         // add transaction into a set, client set and individually
-        tx.z_add(TRANSACTIONS_SET_KEY, tx_id.as_ref(), Self::time_microsecond_score(&Utc::now()));
+        // transactions are scored by creation time, which is what client history is paged by
+        tx.z_add(TRANSACTIONS_SET_KEY, tx_id.as_ref(), Self::time_microsecond_score(&transaction.creation_time));
+        tx.set_nx(store_key!(TRANSACTION_KEY, ":", tx_id), &serialized_transaction);
+        tx.set_nx(
+            store_key!(TRANSACTION_REVISION_KEY, ":", tx_id, ":", transaction.revision_number),
+            &serialized_transaction,
+        );
+        tx.z_add(
+            store_key!(TRANSACTIONS_SET_KEY, ":", CLIENT_KEY, ":", client_id),
+            tx_id.as_ref(),
+            Self::time_microsecond_score(&transaction.creation_time),
+        );
+        // the fee is only charged if the tx goes through
+        if let Some(fee) = fee {
+            Self::add_fee_transaction(&mut tx, fee)?;
+        }
+
+        match self.backend.exec_atomic_write(tx).await? {
+            true => Ok(()),
+            false => Err(Error::Contention),
+        }
+    }
+
+    // Like process_transaction, but updates both of the accounts a conversion moves funds between.
+    // If the target account is new, opened_target is its opened state and the account is created in
+    // the same write, which fails with contention if it was created concurrently.
+    pub async fn process_conversion(
+        &self,
+        source: &Client,
+        target: &Client,
+        opened_target: Option<&Client>,
+        lock: &ClientLock,
+        transaction: &Transaction,
+        fee: Option<&Transaction>,
+        events: &[model::Event],
+        entry: &model::JournalEntry,
+    ) -> Result<()> {
+        let client_id = model::Id::from(source.id as u32);
+        // whether each account is opened by this write
+        let accounts = [
+            (source, source.account_id(), Self::serialize(source)?, false),
+            (target, target.account_id(), Self::serialize(target)?, opened_target.is_some()),
+        ];
+        let serialized_transaction = Self::serialize(transaction)?;
+        let tx_id = model::Id::from(transaction.tx);
+
+        let mut tx = AtomicWriteOperation::new();
+        Self::add_client_lock(&mut tx, lock)?;
+        for (client, account_id, serialized_client, is_opened) in &accounts {
+            tx.z_add(CLIENTS_SET_KEY, account_id.as_ref(), Self::time_microsecond_score(&client.revision_time));
+            if *is_opened {
+                tx.set_nx(store_key!(CLIENT_KEY, ":", account_id), serialized_client);
+            } else {
+                tx.set(store_key!(CLIENT_KEY, ":", account_id), serialized_client);
+            }
+            tx.set_nx(store_key!(CLIENT_REVISION_KEY, ":", account_id, ":", client.revision_number), serialized_client);
+        }
+        if let Some(opened) = opened_target {
+            let account_id = opened.account_id();
+            tx.z_add(
+                store_key!(CLIENT_ACCOUNTS_SET_KEY, ":", client_id),
+                account_id.as_ref(),
+                Self::time_microsecond_score(&opened.creation_time),
+            );
+            tx.set_nx(
+                store_key!(CLIENT_REVISION_KEY, ":", account_id, ":", opened.revision_number),
+                &Self::serialize(opened)?,
+            );
+            Self::add_event(&mut tx, &model::Event::opened(opened))?;
+        }
+        for event in events {
+            Self::add_event(&mut tx, event)?;
+        }
+        Self::add_journal_entry(&mut tx, entry)?;
+
+        tx.z_add(TRANSACTIONS_SET_KEY, tx_id.as_ref(), Self::time_microsecond_score(&transaction.creation_time));
+        tx.set_nx(store_key!(TRANSACTION_KEY, ":", tx_id), &serialized_transaction);
+        tx.set_nx(
+            store_key!(TRANSACTION_REVISION_KEY, ":", tx_id, ":", transaction.revision_number),
+            &serialized_transaction,
+        );
+        tx.z_add(
+            store_key!(TRANSACTIONS_SET_KEY, ":", CLIENT_KEY, ":", client_id),
+            tx_id.as_ref(),
+            Self::time_microsecond_score(&transaction.creation_time),
+        );
+        // the fee is only charged if the tx goes through
+        if let Some(fee) = fee {
+            Self::add_fee_transaction(&mut tx, fee)?;
+        }
+
+        match self.backend.exec_atomic_write(tx).await? {
+            true => Ok(()),
+            false => Err(Error::Contention),
+        }
+    }
+
+    // Same as process_transaction, with the adjustment's reason stored under its tx id.
+    pub async fn process_adjustment(
+        &self,
+        client: &Client,
+        lock: &ClientLock,
+        adjustment: &model::Adjustment,
+        event: &model::Event,
+        entry: &model::JournalEntry,
+    ) -> Result<()> {
+        let serialized_client = Self::serialize(client)?;
+        let client_id = model::Id::from(client.id as u32);
+        let account_id = client.account_id();
+
+        let transaction = &adjustment.transaction;
+        let serialized_transaction = Self::serialize(transaction)?;
+        let serialized_adjustment = Self::serialize(adjustment)?;
+        let tx_id = transaction.id();
+
+        let mut tx = AtomicWriteOperation::new();
+        Self::add_client_lock(&mut tx, lock)?;
+        tx.z_add(CLIENTS_SET_KEY, account_id.as_ref(), Self::time_microsecond_score(&client.revision_time));
+        tx.set(store_key!(CLIENT_KEY, ":", account_id), &serialized_client);
+        tx.set_nx(
+            store_key!(CLIENT_REVISION_KEY, ":", account_id, ":", client.revision_number),
+            &serialized_client,
+        );
+        Self::add_event(&mut tx, event)?;
+        Self::add_journal_entry(&mut tx, entry)?;
+
+        tx.z_add(TRANSACTIONS_SET_KEY, tx_id.as_ref(), Self::time_microsecond_score(&transaction.creation_time));
         tx.set_nx(store_key!(TRANSACTION_KEY, ":", tx_id), &serialized_transaction);
         tx.set_nx(
             store_key!(TRANSACTION_REVISION_KEY, ":", tx_id, ":", transaction.revision_number),
@@ -36,8 +183,9 @@ impl<B: Backend + Sync> Store<B> {
         tx.z_add(
             store_key!(TRANSACTIONS_SET_KEY, ":", CLIENT_KEY, ":", client_id),
             tx_id.as_ref(),
-            Self::time_microsecond_score(&Utc::now()),
+            Self::time_microsecond_score(&transaction.creation_time),
         );
+        tx.set_nx(store_key!(ADJUSTMENT_KEY, ":", tx_id), &serialized_adjustment);
 
         match self.backend.exec_atomic_write(tx).await? {
             true => Ok(()),
@@ -45,6 +193,63 @@ impl<B: Backend + Sync> Store<B> {
         }
     }
 
+    // Adds a fee to the given write, in the set of transactions, the client's transactions and the
+    // fees of the tx it was charged for.
+    pub(crate) fn add_fee_transaction(tx: &mut AtomicWriteOperation, fee: &Transaction) -> Result<()> {
+        let serialized = Self::serialize(fee)?;
+        let fee_id = fee.id();
+        let score = Self::time_microsecond_score(&fee.creation_time);
+
+        tx.z_add(TRANSACTIONS_SET_KEY, fee_id.to_vec(), score);
+        tx.set_nx(store_key!(TRANSACTION_KEY, ":", fee_id), serialized.clone());
+        tx.set_nx(store_key!(TRANSACTION_REVISION_KEY, ":", fee_id, ":", fee.revision_number), serialized);
+        tx.z_add(
+            store_key!(TRANSACTIONS_SET_KEY, ":", CLIENT_KEY, ":", model::Id::from(fee.client as u32)),
+            fee_id.to_vec(),
+            score,
+        );
+        tx.z_add(store_key!(TRANSACTION_FEES_SET_KEY, ":", model::Id::from(fee.tx)), fee_id.to_vec(), score);
+        return Ok(());
+    }
+
+    // Gets the fees charged for a tx, oldest first.
+    pub async fn get_transaction_fees(&self, tx_id: u32) -> Result<Vec<Transaction>> {
+        let id = model::Id::from(tx_id);
+        self.get_by_score(
+            store_key!(TRANSACTION_FEES_SET_KEY, ":", id),
+            f64::NEG_INFINITY,
+            f64::INFINITY,
+            0,
+            TRANSACTION_KEY,
+        )
+        .await
+    }
+
+    pub async fn get_adjustment_by_tx_id(&self, tx_id: u32) -> Result<Option<model::Adjustment>> {
+        let id = model::adjustment_transaction_id(tx_id);
+        if let Some(v) = self.backend.get(store_key!(ADJUSTMENT_KEY, ":", id)).await? {
+            let ret: model::Adjustment = Self::deserialize(v.as_ref())?;
+            return Ok(Some(ret));
+        } else {
+            return Ok(None);
+        }
+    }
+
+    // Claims a tx id for the client if no client has claimed it yet. Returns the client holding
+    // the claim.
+    pub async fn claim_transaction_id(&self, tx_id: u32, client_id: u16) -> Result<u16> {
+        let key = store_key!(TRANSACTION_CLAIM_KEY, ":", model::Id::from(tx_id));
+        let mut tx = AtomicWriteOperation::new();
+        tx.set_nx(key.clone(), Self::serialize(&client_id)?);
+        if self.backend.exec_atomic_write(tx).await? {
+            return Ok(client_id);
+        }
+        match self.backend.get(key).await? {
+            Some(v) => Self::deserialize(v.as_ref()),
+            None => Err(Error::Contention),
+        }
+    }
+
     pub async fn get_transaction_by_id(&self, tx_id: u32) -> Result<Option<Transaction>> {
         let id = model::Id::from(tx_id);
         if let Some(v) = self.backend.get(store_key!(TRANSACTION_KEY, ":", id)).await? {
@@ -60,4 +265,33 @@ impl<B: Backend + Sync> Store<B> {
         self.get_by_time_range(store_key!(TRANSACTIONS_SET_KEY, ":", CLIENT_KEY, ":", id), min, max, limit, TRANSACTION_KEY)
             .await
     }
+
+    // Records a resubmitted transaction in the dedup index, both globally and for its tx id.
+    pub async fn add_duplicate_transaction(&self, duplicate: &DuplicateTransaction) -> Result<()> {
+        let serialized = Self::serialize(duplicate)?;
+        // adjustments are indexed apart from regular transactions using the same tx id
+        let tx_id = duplicate.transaction.id();
+        let score = Self::time_microsecond_score(&duplicate.creation_time);
+
+        let mut tx = AtomicWriteOperation::new();
+        tx.z_add(TRANSACTION_DUPLICATES_SET_KEY, &serialized, score);
+        tx.z_add(store_key!(TRANSACTION_DUPLICATES_SET_KEY, ":", tx_id), &serialized, score);
+
+        match self.backend.exec_atomic_write(tx).await? {
+            true => Ok(()),
+            false => Err(Error::Contention),
+        }
+    }
+
+    pub async fn get_duplicate_transactions_by_tx_id(&self, tx_id: u32) -> Result<Vec<DuplicateTransaction>> {
+        let id = model::Id::from(tx_id);
+        self.get_by_score(
+            store_key!(TRANSACTION_DUPLICATES_SET_KEY, ":", id),
+            f64::NEG_INFINITY,
+            f64::INFINITY,
+            0,
+            TRANSACTION_KEY,
+        )
+        .await
+    }
 }
@@ -9,6 +9,7 @@ class TransactionType(Enum):
     DEPOSIT = "deposit"
     WITHDRAWAL = "withdrawal"
     DISPUTE = "dispute"
+    CONVERSION = "conversion"
     RESOLVE = "resolve"
     CHARGEBACK = "chargeback"
 
@@ -19,6 +20,10 @@ class Transaction:
     client: int
     tx: int
     amount: float
+    # left blank for the default currency
+    currency: str = None
+    # only set for conversions
+    to_currency: str = None
 
     def as_csv_dict(self) -> dict:
         return {
@@ -26,12 +31,37 @@ class Transaction:
             "client": self.client,
             "tx": self.tx,
             "amount": float(self.amount),
+            "currency": self.currency or "",
+            "to_currency": self.to_currency or "",
         }
 
-    def fieldnames() -> list:
+    def fieldnames(with_currency: bool = False, with_to_currency: bool = False) -> list:
+        if with_to_currency:
+            return ["type", "client", "tx", "amount", "currency", "to_currency"]
+        if with_currency:
+            return ["type", "client", "tx", "amount", "currency"]
         return ["type", "client", "tx", "amount"]
 
 
+@dataclass
+class MalformedRow:
+    # written as is, for rows the engine can't parse
+    type: str
+    client: str
+    tx: str
+    amount: str
+    currency: str = None
+    to_currency: str = None
+
+    def as_csv_dict(self) -> dict:
+        return {
+            "type": self.type,
+            "client": self.client,
+            "tx": self.tx,
+            "amount": self.amount,
+        }
+
+
 @dataclass
 class Client:
     client: int
@@ -39,6 +69,8 @@ class Client:
     held: float
     total: float
     locked: bool
+    currency: str = None
+    fees: float = None
 
     def as_csv_dict(self) -> dict:
         return {
@@ -47,10 +79,17 @@ class Client:
             "held": float(self.held),
             "total": float(self.total),
             "locked": "true" if self.locked else "false",
+            "currency": self.currency or "",
+            "fees": float(self.fees or 0.0),
         }
 
-    def fieldnames() -> list:
-        return ["client", "available", "held", "total", "locked"]
+    def fieldnames(with_currency: bool = False, with_fees: bool = False) -> list:
+        ret = ["client", "available", "held", "total", "locked"]
+        if with_currency:
+            ret.append("currency")
+        if with_fees:
+            ret.append("fees")
+        return ret
 
 
 def write_test_data(
@@ -59,10 +98,18 @@ def write_test_data(
     final_accounts: list[Client],
     open_mode: str = "w",
 ):
+    # the currency columns are only written if some row has a currency
+    with_currency = any(x.currency for x in transactions + final_accounts)
+    with_to_currency = any(tx.to_currency for tx in transactions)
+    with_fees = any(c.fees for c in final_accounts)
+
     test_data_path = file_name + ".csv"
     with open(file_path(test_data_path), open_mode, newline="") as csvfile:
         writer = csv.DictWriter(
-            csvfile, fieldnames=Transaction.fieldnames(), delimiter=","
+            csvfile,
+            fieldnames=Transaction.fieldnames(with_currency, with_to_currency),
+            delimiter=",",
+            extrasaction="ignore",
         )
         writer.writeheader()
         for tx in transactions:
@@ -70,7 +117,12 @@ def write_test_data(
 
     test_data_expected_path = file_name + "_expected.csv"
     with open(file_path(test_data_expected_path), open_mode, newline="") as csvfile:
-        writer = csv.DictWriter(csvfile, fieldnames=Client.fieldnames(), delimiter=",")
+        writer = csv.DictWriter(
+            csvfile,
+            fieldnames=Client.fieldnames(with_currency, with_fees),
+            delimiter=",",
+            extrasaction="ignore",
+        )
         writer.writeheader()
         for client in final_accounts:
             writer.writerow(client.as_csv_dict())
@@ -147,6 +199,331 @@ def dispute_charge_backtest():
     write_test_data(file_name, transactions, final_accounts)
 
 
+def dispute_withdrawal_test():
+    transactions = [
+        Transaction(TransactionType.DEPOSIT, 1, 1, 10.0),
+        Transaction(TransactionType.WITHDRAWAL, 1, 2, 4.0),
+        Transaction(TransactionType.DISPUTE, 1, 2, 0.0),
+        Transaction(TransactionType.RESOLVE, 1, 2, 0.0),
+        Transaction(TransactionType.DEPOSIT, 2, 3, 10.0),
+        Transaction(TransactionType.WITHDRAWAL, 2, 4, 4.0),
+        Transaction(TransactionType.DISPUTE, 2, 4, 0.0),
+        Transaction(TransactionType.CHARGEBACK, 2, 4, 0.0),
+        Transaction(TransactionType.DEPOSIT, 3, 5, 5.0),
+        Transaction(TransactionType.WITHDRAWAL, 3, 6, 5.0),
+        Transaction(TransactionType.DISPUTE, 3, 6, 0.0),
+    ]
+
+    write_test_data(
+        "dispute_withdrawal_reject",
+        transactions,
+        [
+            Client(1, 6.0, 0.0, 6.0, False),
+            Client(2, 6.0, 0.0, 6.0, False),
+            Client(3, 0.0, 0.0, 0.0, False),
+        ],
+    )
+    write_test_data(
+        "dispute_withdrawal_hold",
+        transactions,
+        [
+            Client(1, 6.0, 0.0, 6.0, False),
+            Client(2, 10.0, 0.0, 10.0, True),
+            Client(3, 0.0, 5.0, 5.0, False),
+        ],
+    )
+
+
+def redispute_test():
+    transactions = [
+        Transaction(TransactionType.DEPOSIT, 1, 1, 10.0),
+        Transaction(TransactionType.DISPUTE, 1, 1, 0.0),
+        Transaction(TransactionType.RESOLVE, 1, 1, 0.0),
+        Transaction(TransactionType.DISPUTE, 1, 1, 0.0),
+        Transaction(TransactionType.DEPOSIT, 2, 2, 5.0),
+        Transaction(TransactionType.DISPUTE, 2, 2, 0.0),
+        Transaction(TransactionType.RESOLVE, 2, 2, 0.0),
+        Transaction(TransactionType.DISPUTE, 2, 2, 0.0),
+        Transaction(TransactionType.RESOLVE, 2, 2, 0.0),
+        Transaction(TransactionType.DISPUTE, 2, 2, 0.0),
+        Transaction(TransactionType.DEPOSIT, 3, 3, 5.0),
+        Transaction(TransactionType.DEPOSIT, 3, 4, 1.0),
+        Transaction(TransactionType.DISPUTE, 3, 4, 0.0),
+        Transaction(TransactionType.CHARGEBACK, 3, 4, 0.0),
+        Transaction(TransactionType.DISPUTE, 3, 4, 0.0),
+    ]
+
+    write_test_data(
+        "redispute_never",
+        transactions,
+        [
+            Client(1, 10.0, 0.0, 10.0, False),
+            Client(2, 5.0, 0.0, 5.0, False),
+            Client(3, 5.0, 0.0, 5.0, True),
+        ],
+    )
+    write_test_data(
+        "redispute_limit",
+        transactions,
+        [
+            Client(1, 0.0, 10.0, 10.0, False),
+            Client(2, 5.0, 0.0, 5.0, False),
+            Client(3, 5.0, 0.0, 5.0, True),
+        ],
+    )
+    write_test_data(
+        "redispute_after_resolve",
+        transactions,
+        [
+            Client(1, 0.0, 10.0, 10.0, False),
+            Client(2, 0.0, 5.0, 5.0, False),
+            Client(3, 5.0, 0.0, 5.0, True),
+        ],
+    )
+
+
+def locked_client_test():
+    transactions = [
+        Transaction(TransactionType.DEPOSIT, 1, 1, 10.0),
+        Transaction(TransactionType.DEPOSIT, 1, 2, 5.0),
+        Transaction(TransactionType.DEPOSIT, 1, 3, 2.0),
+        Transaction(TransactionType.DISPUTE, 1, 1, 0.0),
+        Transaction(TransactionType.DISPUTE, 1, 2, 0.0),
+        Transaction(TransactionType.CHARGEBACK, 1, 1, 0.0),
+        Transaction(TransactionType.RESOLVE, 1, 2, 0.0),
+        Transaction(TransactionType.DISPUTE, 1, 3, 0.0),
+        Transaction(TransactionType.DEPOSIT, 1, 4, 1.0),
+        Transaction(TransactionType.WITHDRAWAL, 1, 5, 1.0),
+    ]
+
+    write_test_data(
+        "locked_client_reject_all",
+        transactions,
+        [Client(1, 2.0, 5.0, 7.0, True)],
+    )
+    write_test_data(
+        "locked_client_allow_dispute_settlement",
+        transactions,
+        [Client(1, 7.0, 0.0, 7.0, True)],
+    )
+    write_test_data(
+        "locked_client_allow_disputes",
+        transactions,
+        [Client(1, 5.0, 2.0, 7.0, True)],
+    )
+
+
+def negative_balance_test():
+    transactions = [
+        Transaction(TransactionType.DEPOSIT, 1, 1, 10.0),
+        Transaction(TransactionType.WITHDRAWAL, 1, 2, 6.0),
+        Transaction(TransactionType.DISPUTE, 1, 1, 0.0),
+        Transaction(TransactionType.DEPOSIT, 2, 3, 10.0),
+        Transaction(TransactionType.WITHDRAWAL, 2, 4, 6.0),
+        Transaction(TransactionType.DISPUTE, 2, 3, 0.0),
+        Transaction(TransactionType.CHARGEBACK, 2, 3, 0.0),
+        Transaction(TransactionType.DEPOSIT, 3, 5, 10.0),
+        Transaction(TransactionType.WITHDRAWAL, 3, 6, 6.0),
+        Transaction(TransactionType.DISPUTE, 3, 5, 0.0),
+        Transaction(TransactionType.RESOLVE, 3, 5, 0.0),
+    ]
+
+    write_test_data(
+        "negative_balance_allow",
+        transactions,
+        [
+            Client(1, -6.0, 10.0, 4.0, False),
+            Client(2, -6.0, 0.0, -6.0, True),
+            Client(3, 4.0, 0.0, 4.0, False),
+        ],
+    )
+    write_test_data(
+        "negative_balance_cap_hold",
+        transactions,
+        [
+            Client(1, 0.0, 4.0, 4.0, False),
+            Client(2, 0.0, 0.0, 0.0, True),
+            Client(3, 4.0, 0.0, 4.0, False),
+        ],
+    )
+    write_test_data(
+        "negative_balance_reject",
+        transactions,
+        [
+            Client(1, 4.0, 0.0, 4.0, False),
+            Client(2, 4.0, 0.0, 4.0, False),
+            Client(3, 4.0, 0.0, 4.0, False),
+        ],
+    )
+
+
+def cross_client_dispute_test():
+    file_name = "cross_client_dispute"
+    transactions = [
+        Transaction(TransactionType.DEPOSIT, 1, 1, 10.0),
+        Transaction(TransactionType.DEPOSIT, 2, 2, 5.0),
+        Transaction(TransactionType.DISPUTE, 2, 1, 0.0),
+        Transaction(TransactionType.DISPUTE, 1, 1, 0.0),
+        Transaction(TransactionType.RESOLVE, 2, 1, 0.0),
+        Transaction(TransactionType.CHARGEBACK, 2, 1, 0.0),
+        Transaction(TransactionType.DISPUTE, 1, 2, 0.0),
+    ]
+    final_accounts = [
+        Client(1, 0.0, 10.0, 10.0, False),
+        Client(2, 5.0, 0.0, 5.0, False),
+    ]
+
+    write_test_data(file_name, transactions, final_accounts)
+
+
+def duplicate_tx_test():
+    file_name = "duplicate_tx"
+    transactions = [
+        Transaction(TransactionType.DEPOSIT, 1, 1, 10.0),
+        Transaction(TransactionType.DEPOSIT, 1, 1, 10.0),
+        Transaction(TransactionType.DEPOSIT, 1, 1, 5.0),
+        Transaction(TransactionType.WITHDRAWAL, 1, 2, 3.0),
+        Transaction(TransactionType.WITHDRAWAL, 1, 2, 3.0),
+        Transaction(TransactionType.DEPOSIT, 2, 2, 4.0),
+    ]
+    final_accounts = [
+        Client(1, 7.0, 0.0, 7.0, False),
+    ]
+
+    write_test_data(file_name, transactions, final_accounts)
+
+
+def precision_test():
+    file_name = "precision"
+    transactions = [
+        Transaction(TransactionType.DEPOSIT, 1, i, 0.1) for i in range(1, 11)
+    ] + [
+        Transaction(TransactionType.DEPOSIT, 1, 11, 0.0001),
+        Transaction(TransactionType.WITHDRAWAL, 1, 12, 0.3333),
+        Transaction(TransactionType.DEPOSIT, 2, 13, 123456.7891),
+    ]
+    final_accounts = [
+        Client(1, 0.6668, 0.0, 0.6668, False),
+        Client(2, 123456.7891, 0.0, 123456.7891, False),
+    ]
+
+    write_test_data(file_name, transactions, final_accounts)
+
+
+def non_positive_amount_test():
+    file_name = "non_positive_amount"
+    transactions = [
+        Transaction(TransactionType.DEPOSIT, 1, 1, 10.0),
+        # negative amounts would otherwise credit a withdrawal and debit a deposit
+        Transaction(TransactionType.WITHDRAWAL, 1, 2, -1000.0),
+        Transaction(TransactionType.DEPOSIT, 1, 3, -5.0),
+        Transaction(TransactionType.DEPOSIT, 1, 4, 0.0),
+        Transaction(TransactionType.WITHDRAWAL, 1, 5, 0.0),
+    ]
+    final_accounts = [
+        Client(1, 10.0, 0.0, 10.0, False),
+    ]
+
+    write_test_data(file_name, transactions, final_accounts)
+
+
+def malformed_record_test():
+    file_name = "malformed_record"
+    transactions = [
+        Transaction(TransactionType.DEPOSIT, 1, 1, 10.0),
+        MalformedRow("deposit", "1", "2", "ten"),
+        MalformedRow("refund", "1", "3", "1.0"),
+        MalformedRow("deposit", "70000", "4", "1.0"),
+        Transaction(TransactionType.WITHDRAWAL, 1, 5, 3.0),
+    ]
+    final_accounts = [
+        Client(1, 7.0, 0.0, 7.0, False),
+    ]
+
+    write_test_data(file_name, transactions, final_accounts)
+
+
+def multi_currency_test():
+    file_name = "multi_currency"
+    transactions = [
+        Transaction(TransactionType.DEPOSIT, 1, 1, 10.0),
+        Transaction(TransactionType.DEPOSIT, 1, 2, 5.0, "EUR"),
+        Transaction(TransactionType.WITHDRAWAL, 1, 3, 2.0, "EUR"),
+        # only 3.0 EUR is available, the USD funds don't count
+        Transaction(TransactionType.WITHDRAWAL, 1, 4, 6.0, "EUR"),
+        Transaction(TransactionType.DEPOSIT, 2, 5, 3.0, "GBP"),
+        # disputes apply in the currency of the disputed tx
+        Transaction(TransactionType.DISPUTE, 1, 2, 0.0),
+        Transaction(TransactionType.DEPOSIT, 1, 6, 1.0, "usd"),
+        Transaction(TransactionType.DISPUTE, 2, 5, 0.0, "GBP"),
+        Transaction(TransactionType.CHARGEBACK, 2, 5, 0.0),
+        # the chargeback locks the client in every currency, not only in GBP
+        Transaction(TransactionType.DEPOSIT, 2, 7, 4.0),
+        Transaction(TransactionType.DEPOSIT, 2, 8, 1.0, "GBP"),
+    ]
+    final_accounts = [
+        Client(1, 11.0, 0.0, 11.0, False, "USD"),
+        Client(1, -2.0, 5.0, 3.0, False, "EUR"),
+        Client(2, 0.0, 0.0, 0.0, True, "GBP"),
+    ]
+
+    write_test_data(file_name, transactions, final_accounts)
+
+
+def conversion_test():
+    file_name = "conversion"
+    # converted with the rates in tests/test_data/fx_rates.csv
+    transactions = [
+        Transaction(TransactionType.DEPOSIT, 1, 1, 10.0),
+        Transaction(TransactionType.CONVERSION, 1, 2, 4.0, "USD", "EUR"),
+        # not enough USD left
+        Transaction(TransactionType.CONVERSION, 1, 3, 10.0, "USD", "EUR"),
+        # there is no EUR to GBP rate
+        Transaction(TransactionType.CONVERSION, 1, 4, 1.0, "EUR", "GBP"),
+        Transaction(TransactionType.CONVERSION, 1, 5, 2.0, "EUR", "USD"),
+        Transaction(TransactionType.CONVERSION, 1, 6, 1.0, "USD", "USD"),
+        Transaction(TransactionType.DEPOSIT, 2, 7, 5.0),
+        Transaction(TransactionType.CONVERSION, 2, 8, 5.0, None, "GBP"),
+        # conversions can't be disputed
+        Transaction(TransactionType.DISPUTE, 1, 2, 0.0),
+    ]
+    final_accounts = [
+        Client(1, 8.2, 0.0, 8.2, False, "USD"),
+        Client(1, 1.6, 0.0, 1.6, False, "EUR"),
+        Client(2, 0.0, 0.0, 0.0, False, "USD"),
+        Client(2, 4.0, 0.0, 4.0, False, "GBP"),
+    ]
+
+    write_test_data(file_name, transactions, final_accounts)
+
+
+def fees_test():
+    file_name = "fees"
+    # charged with the fee schedule of the integration test, converted with the rates in
+    # tests/test_data/fx_rates.csv
+    transactions = [
+        Transaction(TransactionType.DEPOSIT, 1, 1, 100.0),
+        Transaction(TransactionType.WITHDRAWAL, 1, 2, 50.0),
+        # the fee makes it more than what is available
+        Transaction(TransactionType.WITHDRAWAL, 1, 3, 49.0),
+        Transaction(TransactionType.CONVERSION, 1, 4, 5.0, "USD", "EUR"),
+        Transaction(TransactionType.CONVERSION, 1, 5, 20.0, "USD", "EUR"),
+        Transaction(TransactionType.DEPOSIT, 2, 6, 10.0),
+        Transaction(TransactionType.DISPUTE, 2, 6, 0.0),
+        Transaction(TransactionType.CHARGEBACK, 2, 6, 0.0),
+        # smaller than its fee
+        Transaction(TransactionType.DEPOSIT, 3, 7, 0.3),
+    ]
+    final_accounts = [
+        Client(1, 23.8, 0.0, 23.8, False, "USD", 1.2),
+        Client(1, 22.5, 0.0, 22.5, False, "EUR"),
+        Client(2, -5.5, 0.0, -5.5, True, "USD", 5.5),
+        Client(3, 0.0, 0.0, 0.0, False, "USD"),
+    ]
+
+    write_test_data(file_name, transactions, final_accounts)
+
+
 def chungus():
     file_name = "chungus"
     num_rows = 1000
@@ -176,4 +553,16 @@ if __name__ == "__main__":
     dispute_resolve_test()
     dispute_charge_backtest()
     dispute_test()
+    dispute_withdrawal_test()
+    redispute_test()
+    locked_client_test()
+    negative_balance_test()
+    cross_client_dispute_test()
+    duplicate_tx_test()
+    precision_test()
+    non_positive_amount_test()
+    malformed_record_test()
+    multi_currency_test()
+    conversion_test()
+    fees_test()
     chungus()
@@ -1,6 +1,6 @@
 use payment_engine::{
-    api::{self, ClientResponse, PostTransaction, API},
-    app::{App, Config},
+    api::{self, ClientPageResponse, ClientResponse, PostTransaction, API},
+    app::{App, Config, RejectionFormat, RejectionWriter, Session},
     model, store, Result,
 };
 use rocket::local::asynchronous::Client;
@@ -10,7 +10,12 @@ use rocket::{
     http::{ContentType, Status},
 };
 use slog::o;
-use std::{env, path::Path};
+use std::{
+    env,
+    io::{self, Write},
+    path::Path,
+    sync::{Arc, Mutex},
+};
 
 pub async fn new_test_app<F: FnOnce(&mut Config)>(configure: F) -> App {
     let mut config = Config {
@@ -32,8 +37,8 @@ pub fn test_logger() -> slog::Logger {
     slog::Logger::root(drain, o!())
 }
 
-pub async fn new_test_api() -> API {
-    let a = new_test_app(|_| {}).await;
+pub async fn new_test_api_with_config<F: FnOnce(&mut Config)>(configure: F) -> API {
+    let a = new_test_app(configure).await;
     return API::new(test_logger(), a);
 }
 
@@ -43,6 +48,21 @@ pub async fn test_rocket_client(api: API) -> Client {
     return client;
 }
 
+// Follows the client listing's next links until every client has been fetched.
+pub async fn get_all_api_clients(rocket_client: &Client) -> Vec<ClientResponse> {
+    let mut ret = Vec::new();
+    let mut next = Some("/clients".to_string());
+    while let Some(link) = next {
+        let response = rocket_client.get(link).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+
+        let page = response.into_json::<ClientPageResponse>().await.unwrap();
+        ret.extend(page.clients);
+        next = page.next;
+    }
+    return ret;
+}
+
 pub fn parse_expected_data_csv(file_path: String) -> Result<Vec<model::CSVClient>> {
     let mut rdr = csv::ReaderBuilder::new().has_headers(true).trim(csv::Trim::All).from_path(file_path)?;
     let mut raw_record = csv::ByteRecord::new();
@@ -57,14 +77,75 @@ pub fn parse_expected_data_csv(file_path: String) -> Result<Vec<model::CSVClient
 }
 
 pub async fn test_csv_data(file_name: String) {
-    let app = new_test_app(|_| {}).await;
+    test_csv_data_with_config(file_name, |_| {}).await
+}
+
+pub async fn test_csv_data_with_config<F: FnOnce(&mut Config)>(file_name: String, configure: F) {
+    let app = new_test_app(configure).await;
+    let sess = app.new_session(test_logger());
+
+    sess.process_csv(test_data_csv_path(&file_name), None).await.unwrap();
+
+    assert_expected_clients(&sess, &file_name).await;
+}
+
+// Processes a test data csv and returns the rejection report written in the given format.
+pub async fn process_csv_rejections(file_name: &str, format: RejectionFormat) -> String {
+    process_csv_rejections_with_config(file_name, format, |_| {}).await
+}
+
+pub async fn process_csv_rejections_with_config<F: FnOnce(&mut Config)>(file_name: &str, format: RejectionFormat, configure: F) -> String {
+    let app = new_test_app(configure).await;
+    let sess = app.new_session(test_logger());
+
+    let buf = SharedBuffer::default();
+    sess.process_csv(test_data_csv_path(file_name), Some(RejectionWriter::new(buf.clone(), format)))
+        .await
+        .unwrap();
+
+    let report = buf.0.lock().unwrap().clone();
+    return String::from_utf8(report).unwrap();
+}
+
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Processes a test data csv and returns the resulting client accounts, sorted by client.
+pub async fn process_csv_data<F: FnOnce(&mut Config)>(file_name: &str, configure: F) -> Vec<model::CSVClient> {
+    let app = new_test_app(configure).await;
     let sess = app.new_session(test_logger());
 
+    sess.process_csv(test_data_csv_path(file_name), None).await.unwrap();
+
+    let mut all_clients = sess
+        .get_all_clients()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|x| model::CSVClient::from(x))
+        .collect::<Vec<model::CSVClient>>();
+    all_clients.sort();
+    return all_clients;
+}
+
+pub fn test_data_csv_path(file_name: &str) -> String {
     let test_data_path = env::current_dir().unwrap().join(Path::new("tests/test_data/"));
-    let test_data_csv = test_data_path.join(file_name.clone() + ".csv").to_str().unwrap().to_string();
-    let expected_data_csv = test_data_path.join(file_name + "_expected.csv").to_str().unwrap().to_string();
+    return test_data_path.join(file_name.to_string() + ".csv").to_str().unwrap().to_string();
+}
 
-    sess.process_csv(test_data_csv).await.unwrap();
+pub async fn assert_expected_clients(sess: &Session<'_>, file_name: &str) {
+    let test_data_path = env::current_dir().unwrap().join(Path::new("tests/test_data/"));
+    let expected_data_csv = test_data_path.join(file_name.to_string() + "_expected.csv").to_str().unwrap().to_string();
 
     let mut all_clients = sess
         .get_all_clients()
@@ -83,7 +164,11 @@ pub async fn test_csv_data(file_name: String) {
 }
 
 pub async fn test_service_from_csv_data(file_name: String) {
-    let api = new_test_api().await;
+    test_service_from_csv_data_with_config(file_name, |_| {}).await
+}
+
+pub async fn test_service_from_csv_data_with_config<F: FnOnce(&mut Config)>(file_name: String, configure: F) {
+    let api = new_test_api_with_config(configure).await;
     let rocket_client = test_rocket_client(api).await;
 
     let test_data_path = env::current_dir().unwrap().join(Path::new("tests/test_data/"));
@@ -112,13 +197,8 @@ pub async fn test_service_from_csv_data(file_name: String) {
     }
 
     // get all clients account from service and compare to expected data
-    let response = rocket_client.get(rocket::uri!("/clients", api::get_all_clients())).dispatch().await;
-    assert_eq!(response.status(), Status::Ok);
-
-    let mut all_clients = response
-        .into_json::<Vec<ClientResponse>>()
+    let mut all_clients = get_all_api_clients(&rocket_client)
         .await
-        .unwrap()
         .into_iter()
         .map(|x| model::CSVClient::from(x))
         .collect::<Vec<model::CSVClient>>();
@@ -133,7 +213,7 @@ pub async fn test_service_from_csv_data(file_name: String) {
 }
 
 pub async fn test_service_from_csv_data_concurently(file_name: String) {
-    let api = new_test_api().await;
+    let api = new_test_api_with_config(|_| {}).await;
     let rocket_client = test_rocket_client(api).await;
 
     let test_data_path = env::current_dir().unwrap().join(Path::new("tests/test_data/"));
@@ -166,13 +246,8 @@ pub async fn test_service_from_csv_data_concurently(file_name: String) {
     future::join_all(handles).await;
 
     // get all clients account from service and compare to expected data
-    let response = rocket_client.get(rocket::uri!("/clients", api::get_all_clients())).dispatch().await;
-    assert_eq!(response.status(), Status::Ok);
-
-    let mut all_clients = response
-        .into_json::<Vec<ClientResponse>>()
+    let mut all_clients = get_all_api_clients(&rocket_client)
         .await
-        .unwrap()
         .into_iter()
         .map(|x| model::CSVClient::from(x))
         .collect::<Vec<model::CSVClient>>();
@@ -4,6 +4,14 @@ extern crate test;
 
 mod common;
 
+use chrono::Utc;
+use flate2::{write::GzEncoder, Compression};
+use payment_engine::{
+    app::{Config, Fee, FeeSchedule, FeeTier, LockPolicy, NegativeBalancePolicy, RedisputePolicy, RejectionFormat, WithdrawalDisputePolicy},
+    model,
+};
+use std::io::{self, Write};
+
 #[tokio::test]
 async fn test_deposit_withdrawal() {
     let file_name = "deposit_withdrawal".to_string();
@@ -32,9 +40,437 @@ async fn test_dispute_chargeback() {
     common::test_service_from_csv_data(file_name).await;
 }
 
+#[tokio::test]
+async fn test_dispute_withdrawal_reject() {
+    let file_name = "dispute_withdrawal_reject".to_string();
+    common::test_csv_data_with_config(file_name.clone(), |c| c.withdrawal_disputes = WithdrawalDisputePolicy::Reject).await;
+    common::test_service_from_csv_data_with_config(file_name, |c| c.withdrawal_disputes = WithdrawalDisputePolicy::Reject).await;
+}
+
+#[tokio::test]
+async fn test_dispute_withdrawal_hold() {
+    let file_name = "dispute_withdrawal_hold".to_string();
+    common::test_csv_data_with_config(file_name.clone(), |c| c.withdrawal_disputes = WithdrawalDisputePolicy::Hold).await;
+    common::test_service_from_csv_data_with_config(file_name, |c| c.withdrawal_disputes = WithdrawalDisputePolicy::Hold).await;
+}
+
+#[tokio::test]
+async fn test_redispute_never() {
+    let file_name = "redispute_never".to_string();
+    common::test_csv_data_with_config(file_name.clone(), |c| c.redisputes = RedisputePolicy::Never).await;
+    common::test_service_from_csv_data_with_config(file_name, |c| c.redisputes = RedisputePolicy::Never).await;
+}
+
+#[tokio::test]
+async fn test_redispute_limit() {
+    let file_name = "redispute_limit".to_string();
+    common::test_csv_data_with_config(file_name.clone(), |c| c.redisputes = RedisputePolicy::Limit(1)).await;
+    common::test_service_from_csv_data_with_config(file_name, |c| c.redisputes = RedisputePolicy::Limit(1)).await;
+}
+
+#[tokio::test]
+async fn test_redispute_after_resolve() {
+    let file_name = "redispute_after_resolve".to_string();
+    common::test_csv_data_with_config(file_name.clone(), |c| c.redisputes = RedisputePolicy::AfterResolve).await;
+    common::test_service_from_csv_data_with_config(file_name, |c| c.redisputes = RedisputePolicy::AfterResolve).await;
+}
+
+#[tokio::test]
+async fn test_locked_client_reject_all() {
+    let file_name = "locked_client_reject_all".to_string();
+    common::test_csv_data_with_config(file_name.clone(), |c| c.locked_clients = LockPolicy::RejectAll).await;
+    common::test_service_from_csv_data_with_config(file_name, |c| c.locked_clients = LockPolicy::RejectAll).await;
+}
+
+#[tokio::test]
+async fn test_locked_client_allow_dispute_settlement() {
+    let file_name = "locked_client_allow_dispute_settlement".to_string();
+    common::test_csv_data_with_config(file_name.clone(), |c| c.locked_clients = LockPolicy::AllowDisputeSettlement).await;
+    common::test_service_from_csv_data_with_config(file_name, |c| c.locked_clients = LockPolicy::AllowDisputeSettlement).await;
+}
+
+#[tokio::test]
+async fn test_locked_client_allow_disputes() {
+    let file_name = "locked_client_allow_disputes".to_string();
+    common::test_csv_data_with_config(file_name.clone(), |c| c.locked_clients = LockPolicy::AllowDisputes).await;
+    common::test_service_from_csv_data_with_config(file_name, |c| c.locked_clients = LockPolicy::AllowDisputes).await;
+}
+
+#[tokio::test]
+async fn test_non_positive_amount() {
+    let file_name = "non_positive_amount".to_string();
+    common::test_csv_data(file_name.clone()).await;
+    common::test_service_from_csv_data(file_name).await;
+}
+
+#[tokio::test]
+async fn test_malformed_record() {
+    let file_name = "malformed_record".to_string();
+    common::test_csv_data(file_name.clone()).await;
+
+    let report = common::process_csv_rejections(&file_name, RejectionFormat::CSV).await;
+    assert_eq!(
+        report,
+        "line,tx,client,type,reason\n\
+         3,,,,malformed_record\n\
+         4,,,,malformed_record\n\
+         5,,,,malformed_record\n"
+    );
+}
+
+#[tokio::test]
+async fn test_multi_currency() {
+    let file_name = "multi_currency".to_string();
+    common::test_csv_data(file_name.clone()).await;
+    common::test_service_from_csv_data(file_name.clone()).await;
+
+    // every currency balances on its own
+    let app = common::new_test_app(|_| {}).await;
+    let sess = app.new_session(common::test_logger());
+    sess.process_csv(common::test_data_csv_path(&file_name), None).await.unwrap();
+    let trial_balance = sess.trial_balance().await.unwrap();
+    assert!(trial_balance.is_balanced());
+    assert_eq!(
+        trial_balance.totals.iter().map(|t| t.currency.to_string()).collect::<Vec<_>>(),
+        vec!["EUR", "GBP", "USD"]
+    );
+}
+
+#[tokio::test]
+async fn test_conversion() {
+    let file_name = "conversion".to_string();
+    let fx_rates = || Some("tests/test_data/fx_rates.csv".to_string());
+    common::test_csv_data_with_config(file_name.clone(), |c| c.fx_rates = fx_rates()).await;
+    common::test_service_from_csv_data_with_config(file_name.clone(), |c| c.fx_rates = fx_rates()).await;
+
+    let app = common::new_test_app(|c| c.fx_rates = fx_rates()).await;
+    let sess = app.new_session(common::test_logger());
+    sess.process_csv(common::test_data_csv_path(&file_name), None).await.unwrap();
+    assert!(sess.trial_balance().await.unwrap().is_balanced());
+
+    // the rate used is kept on the stored transaction
+    let conversion = sess.get_transaction_by_id(2).await.unwrap().unwrap().conversion.unwrap();
+    assert_eq!(conversion.rate.to_string(), "0.90000000");
+    assert_eq!(conversion.converted_amount, "3.6".parse().unwrap());
+    assert_eq!(conversion.rate_time, None);
+    let conversion = sess.get_transaction_by_id(8).await.unwrap().unwrap().conversion.unwrap();
+    assert_eq!(conversion.rate_time, Some("2000-01-01T00:00:00Z".parse().unwrap()));
+}
+
+// The fee schedule the fees test data is generated for.
+fn configure_fees(c: &mut Config) {
+    let amount = |s: &str| s.parse::<model::Amount>().unwrap();
+    c.fees = FeeSchedule {
+        deposit: Some(Fee::Flat(amount("0.5"))),
+        withdrawal: Some(Fee::Percentage(amount("1"))),
+        conversion: Some(Fee::Tiered(vec![
+            FeeTier {
+                up_to: Some(amount("10")),
+                fee: Fee::Flat(amount("0.1")),
+            },
+            FeeTier {
+                up_to: None,
+                fee: Fee::Percentage(amount("0.5")),
+            },
+        ])),
+        chargeback: Some(Fee::Flat(amount("5"))),
+    };
+    c.fx_rates = Some("tests/test_data/fx_rates.csv".to_string());
+}
+
+#[tokio::test]
+async fn test_fees() {
+    let file_name = "fees".to_string();
+    common::test_csv_data_with_config(file_name.clone(), configure_fees).await;
+    common::test_service_from_csv_data_with_config(file_name.clone(), configure_fees).await;
+
+    let app = common::new_test_app(configure_fees).await;
+    let sess = app.new_session(common::test_logger());
+    sess.process_csv(common::test_data_csv_path(&file_name), None).await.unwrap();
+
+    // fees are linked to the tx they were charged for and listed right after it in client history
+    let fees = sess.get_transaction_fees(6).await.unwrap();
+    assert_eq!(
+        fees.iter().map(|f| (f.charged_for, f.amount)).collect::<Vec<_>>(),
+        vec![
+            (Some(model::RecordType::Deposit), "0.5".parse().unwrap()),
+            (Some(model::RecordType::Chargeback), "5".parse().unwrap())
+        ]
+    );
+    let history = sess.get_all_client_transactions(1).await.unwrap();
+    assert_eq!(
+        history.iter().map(|tx| (tx.record_type, tx.tx)).collect::<Vec<_>>(),
+        vec![
+            (model::RecordType::Deposit, 1),
+            (model::RecordType::Fee, 1),
+            (model::RecordType::Withdrawal, 2),
+            (model::RecordType::Fee, 2),
+            (model::RecordType::Conversion, 4),
+            (model::RecordType::Fee, 4),
+            (model::RecordType::Conversion, 5),
+            (model::RecordType::Fee, 5),
+        ]
+    );
+
+    let trial_balance = sess.trial_balance().await.unwrap();
+    assert!(trial_balance.is_balanced());
+    let fee_income = trial_balance
+        .accounts
+        .iter()
+        .find(|a| a.account == model::LedgerAccount::FeeIncome(model::Currency::USD))
+        .unwrap();
+    assert_eq!((fee_income.debit, fee_income.credit), (model::Amount::ZERO, "6.7".parse().unwrap()));
+}
+
+#[tokio::test]
+async fn test_negative_balance_allow() {
+    let file_name = "negative_balance_allow".to_string();
+    common::test_csv_data_with_config(file_name.clone(), |c| c.negative_balances = NegativeBalancePolicy::Allow).await;
+    common::test_service_from_csv_data_with_config(file_name, |c| c.negative_balances = NegativeBalancePolicy::Allow).await;
+}
+
+#[tokio::test]
+async fn test_negative_balance_cap_hold() {
+    let file_name = "negative_balance_cap_hold".to_string();
+    common::test_csv_data_with_config(file_name.clone(), |c| c.negative_balances = NegativeBalancePolicy::CapHold).await;
+    common::test_service_from_csv_data_with_config(file_name, |c| c.negative_balances = NegativeBalancePolicy::CapHold).await;
+}
+
+#[tokio::test]
+async fn test_negative_balance_reject() {
+    let file_name = "negative_balance_reject".to_string();
+    common::test_csv_data_with_config(file_name.clone(), |c| c.negative_balances = NegativeBalancePolicy::Reject).await;
+    common::test_service_from_csv_data_with_config(file_name, |c| c.negative_balances = NegativeBalancePolicy::Reject).await;
+}
+
+#[tokio::test]
+async fn test_cross_client_dispute() {
+    let file_name = "cross_client_dispute".to_string();
+    common::test_csv_data(file_name.clone()).await;
+    common::test_service_from_csv_data(file_name).await;
+}
+
+#[tokio::test]
+async fn test_duplicate_tx() {
+    let file_name = "duplicate_tx".to_string();
+    common::test_csv_data(file_name.clone()).await;
+    common::test_service_from_csv_data(file_name).await;
+}
+
+#[tokio::test]
+async fn test_precision() {
+    let file_name = "precision".to_string();
+    common::test_csv_data(file_name.clone()).await;
+    common::test_service_from_csv_data(file_name).await;
+}
+
+#[tokio::test]
+async fn test_rejection_report() {
+    let report = common::process_csv_rejections("cross_client_dispute", RejectionFormat::CSV).await;
+    assert_eq!(
+        report,
+        "line,tx,client,type,reason\n\
+         4,1,2,dispute,client_mismatch\n\
+         6,1,2,resolve,client_mismatch\n\
+         7,1,2,chargeback,client_mismatch\n\
+         8,2,1,dispute,client_mismatch\n"
+    );
+
+    let report = common::process_csv_rejections("dispute_withdrawal_reject", RejectionFormat::JSONL).await;
+    let rows = report.lines().map(|row| serde_json::from_str(row).unwrap()).collect::<Vec<serde_json::Value>>();
+    let reasons = rows
+        .iter()
+        .map(|row| (row["line"].as_u64().unwrap(), row["reason"].as_str().unwrap()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        reasons,
+        vec![
+            (4, "withdrawal_dispute_not_allowed"),
+            (5, "not_disputed"),
+            (8, "withdrawal_dispute_not_allowed"),
+            (9, "not_disputed"),
+            (12, "withdrawal_dispute_not_allowed"),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_accounts_as_of() {
+    let file_name = "dispute_resolve";
+    let app = common::new_test_app(|_| {}).await;
+    let sess = app.new_session(common::test_logger());
+    sess.process_csv(common::test_data_csv_path(file_name), None).await.unwrap();
+    let as_of = Utc::now();
+
+    // later activity on existing and new clients doesn't show up as of the earlier time
+    for (client, tx) in [(1, 1000), (1000, 1001)] {
+        let deposit = model::Transaction::from(model::CSVTransaction {
+            record_type: model::RecordType::Deposit,
+            client,
+            tx,
+            amount: Some(model::Amount::from(100)),
+            currency: None,
+            to_currency: None,
+        });
+        sess.process_transaction(deposit).await.unwrap();
+    }
+
+    let mut clients = sess
+        .get_all_clients_as_of(as_of)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(model::CSVClient::from)
+        .collect::<Vec<_>>();
+    clients.sort();
+    let mut expected = common::parse_expected_data_csv(common::test_data_csv_path(&format!("{file_name}_expected"))).unwrap();
+    expected.sort();
+    assert_eq!(clients, expected);
+}
+
+#[tokio::test]
+async fn test_gzip_reader() {
+    let file_name = "dispute_resolve";
+    let app = common::new_test_app(|_| {}).await;
+    let sess = app.new_session(common::test_logger());
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&std::fs::read(common::test_data_csv_path(file_name)).unwrap()).unwrap();
+    sess.process_csv_reader(io::Cursor::new(encoder.finish().unwrap()), None).await.unwrap();
+
+    common::assert_expected_clients(&sess, file_name).await;
+}
+
+#[tokio::test]
+async fn test_async_reader() {
+    let file_name = "dispute_chargeback";
+    let app = common::new_test_app(|_| {}).await;
+    let sess = app.new_session(common::test_logger());
+
+    let file = tokio::fs::File::open(common::test_data_csv_path(file_name)).await.unwrap();
+    sess.process_csv_async_reader(file, None).await.unwrap();
+
+    common::assert_expected_clients(&sess, file_name).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_sharded_matches_sequential() {
+    for file_name in [
+        "chungus",
+        "deposit_withdrawal",
+        "dispute",
+        "dispute_resolve",
+        "dispute_chargeback",
+        "precision",
+        "cross_client_dispute",
+        "duplicate_tx",
+        "multi_currency",
+        "non_positive_amount",
+        "malformed_record",
+    ] {
+        let sequential = common::process_csv_data(file_name, |c| c.csv_workers = 1).await;
+        let sharded = common::process_csv_data(file_name, |c| c.csv_workers = 4).await;
+        assert_eq!(sequential, sharded, "{}", file_name);
+    }
+    common::test_csv_data_with_config("chungus".to_string(), |c| c.csv_workers = 4).await;
+}
+
+// Tx ids reused by another client are rejected the same way whichever worker gets to them first.
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_sharded_rejection_report() {
+    let report = common::process_csv_rejections_with_config("duplicate_tx", RejectionFormat::CSV, |c| c.csv_workers = 4).await;
+    let mut rows = report.lines().collect::<Vec<_>>();
+    rows[1..].sort();
+    assert_eq!(
+        rows,
+        vec![
+            "line,tx,client,type,reason",
+            "4,1,1,deposit,transaction_id_conflict",
+            "7,2,2,deposit,transaction_id_conflict",
+        ]
+    );
+}
+
+// The config is validated before any record is processed.
+#[test]
+fn test_invalid_config() {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_payment-engine"))
+        .arg(common::test_data_csv_path("deposit_withdrawal"))
+        .env("PS_APP_FEES", "Deposit: !Percentage 150")
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("fee percentage 150.0000 is above 100"));
+}
+
 #[cfg(feature = "benchmarks")]
 #[bench]
 fn bench_chungus(b: &mut ::test::Bencher) {
     let rt = rocket::tokio::runtime::Builder::new_multi_thread().enable_all().build().unwrap();
     b.iter(|| rt.block_on(async { common::test_service_from_csv_data_concurently("chungus".to_string()).await }));
 }
+
+#[tokio::test]
+async fn test_verify_all() {
+    let cases: Vec<(&str, Box<dyn FnOnce(&mut payment_engine::app::Config)>)> = vec![
+        ("dispute_chargeback", Box::new(|_| {})),
+        ("dispute_withdrawal_hold", Box::new(|c| c.withdrawal_disputes = WithdrawalDisputePolicy::Hold)),
+        ("redispute_after_resolve", Box::new(|c| c.redisputes = RedisputePolicy::AfterResolve)),
+        ("locked_client_allow_disputes", Box::new(|c| c.locked_clients = LockPolicy::AllowDisputes)),
+        ("negative_balance_cap_hold", Box::new(|c| c.negative_balances = NegativeBalancePolicy::CapHold)),
+        ("multi_currency", Box::new(|_| {})),
+        ("conversion", Box::new(|c| c.fx_rates = Some("tests/test_data/fx_rates.csv".to_string()))),
+        ("fees", Box::new(configure_fees)),
+    ];
+    for (file_name, configure) in cases {
+        let app = common::new_test_app(configure).await;
+        let sess = app.new_session(common::test_logger());
+        sess.process_csv(common::test_data_csv_path(file_name), None).await.unwrap();
+        assert_eq!(sess.verify_all().await.unwrap(), vec![], "{}", file_name);
+    }
+}
+
+#[tokio::test]
+async fn test_replay_clients() {
+    let app = common::new_test_app(|c| c.withdrawal_disputes = WithdrawalDisputePolicy::Hold).await;
+    let sess = app.new_session(common::test_logger());
+    sess.process_csv(common::test_data_csv_path("dispute_withdrawal_hold"), None).await.unwrap();
+
+    let clients = sess.get_all_clients().await.unwrap();
+    assert!(!clients.is_empty());
+    for client in clients {
+        assert_eq!(sess.replay_client(client.id, client.currency).await.unwrap(), Some(client));
+    }
+}
+
+#[tokio::test]
+async fn test_trial_balance() {
+    let app = common::new_test_app(|c| c.negative_balances = NegativeBalancePolicy::CapHold).await;
+    let sess = app.new_session(common::test_logger());
+    sess.process_csv(common::test_data_csv_path("negative_balance_cap_hold"), None).await.unwrap();
+
+    let trial_balance = sess.trial_balance().await.unwrap();
+    assert!(trial_balance.is_balanced());
+    assert!(trial_balance.mismatches.is_empty());
+
+    // client accounts are credit balances that have to match the stored clients
+    for client in sess.get_all_clients().await.unwrap() {
+        for (account, expected) in [
+            (model::LedgerAccount::ClientAvailable(client.id, client.currency), client.available),
+            (model::LedgerAccount::ClientHeld(client.id, client.currency), client.held),
+        ] {
+            let balance = trial_balance.accounts.iter().find(|a| a.account == account);
+            let actual = balance.map(|a| a.credit.checked_sub(a.debit).unwrap()).unwrap_or(model::Amount::ZERO);
+            assert_eq!(actual, expected, "{}", account);
+        }
+    }
+
+    // client 2's chargeback could only take back the 4.0 that was still available
+    let loss = trial_balance
+        .accounts
+        .iter()
+        .find(|a| a.account == model::LedgerAccount::ChargebackLoss(model::Currency::USD))
+        .unwrap();
+    assert_eq!((loss.debit, loss.credit), (model::Amount::from(6), model::Amount::ZERO));
+}